@@ -1,4 +1,9 @@
 fn main() {
+    // only the SDL frontend needs to link against SDL2, not the library or chip-chop-dis
+    if std::env::var_os("CARGO_FEATURE_SDL").is_none() {
+        return;
+    }
+
     println!("cargo:rustc-link-search=framework=/Library/Frameworks");
-    println!("cargo:rustc-link-lib=SDL2");
-}
\ No newline at end of file
+    println!("cargo:rustc-link-arg-bin=chip-chop=-lSDL2");
+}
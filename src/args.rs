@@ -0,0 +1,43 @@
+//! Command line splitting shared by the `chip-chop` and `chip-chop-dis` binaries.
+//!
+//! Flags take their value either as the next argument (`--speed 2`) or inline
+//! (`--speed=2`). [`Args`] hides the difference, so each binary only has to
+//! match on flag names.
+
+/// Walks the command line one flag or positional argument at a time.
+pub struct Args<I: Iterator<Item = String>> {
+    args: I,
+    // the value of a `--flag=value` argument, until `value` takes it
+    inline_value: Option<String>,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    pub fn new(args: I) -> Self {
+        Args { args, inline_value: None }
+    }
+
+    /// The value of the flag last returned by `next`: its inline value, else the argument after it.
+    pub fn value(&mut self, flag: &str) -> Result<String, String> {
+        self.inline_value.take()
+            .or_else(|| self.args.next())
+            .ok_or(format!("{} expects a value", flag))
+    }
+}
+
+impl<I: Iterator<Item = String>> Iterator for Args<I> {
+    type Item = String;
+
+    /// The next argument, with a `--flag=value` argument cut down to `--flag`.
+    fn next(&mut self) -> Option<String> {
+        let arg = self.args.next()?;
+        self.inline_value = None;
+
+        match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => {
+                self.inline_value = Some(value.to_string());
+                Some(flag.to_string())
+            }
+            _ => Some(arg),
+        }
+    }
+}
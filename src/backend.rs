@@ -0,0 +1,83 @@
+//! Traits connecting the headless machine to a frontend.
+//!
+//! [`Emulator`](crate::emulator::Emulator) is generic over these, so SDL, a
+//! terminal renderer or a test harness can all drive the same core.
+
+use crate::components::{AudioPattern, Display, Keypad, Sample};
+use crate::error::CpuFault;
+use crate::emulator::EmulatorState;
+
+pub trait VideoSink {
+    /// Draws the framebuffer into the sink's back buffer.
+    fn update_display(&mut self, display: &Display);
+
+    /// Presents whatever was last drawn by `update_display`.
+    fn render_canvas(&mut self) {}
+
+    /// Called once when the CPU halts; the framebuffer stays frozen afterwards.
+    fn show_fault(&mut self, _fault: &CpuFault) {}
+
+    /// Called when FX0A starts (true) or stops (false) waiting for a key.
+    fn show_waiting_for_key(&mut self, _waiting: bool) {}
+
+    /// Shows a short status line, e.g. the outcome of a hotkey.
+    fn show_message(&mut self, _message: &str) {}
+}
+
+pub trait AudioSink {
+    fn start_sound(&mut self);
+    fn stop_sound(&mut self);
+
+    /// Switches between the classic buzzer (None) and an XO-CHIP pattern; called whenever it changes.
+    fn set_pattern(&mut self, _pattern: Option<AudioPattern>) {}
+
+    /// Starts a MEGA-CHIP digitised sound, replacing any that is already playing.
+    fn play_sample(&mut self, _sample: &Sample) {}
+
+    fn stop_sample(&mut self) {}
+}
+
+/// Emulator actions bound to keys outside the CHIP-8 keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    SaveState(usize),
+    LoadState(usize),
+    /// Stops the program and opens the debugger console.
+    Debug,
+}
+
+pub trait InputSource {
+    /// Polls for pending input, updating `keypad` and returning the state the emulator should move to.
+    fn check_inputs(&mut self, keypad: &mut Keypad) -> EmulatorState;
+
+    /// Hands over a hotkey pressed during the last `check_inputs`, if any.
+    fn take_hotkey(&mut self) -> Option<Hotkey> {
+        None
+    }
+
+    /// Whether the rewind key is being held down.
+    fn rewind_held(&self) -> bool {
+        false
+    }
+
+    /// Updates the CHIP-8X second keypad to the keys held as of the last `check_inputs`.
+    fn check_second_keypad(&mut self, _keypad: &mut Keypad) {}
+}
+
+/// A backend that discards video and audio and never presses a key.
+pub struct Headless;
+
+impl VideoSink for Headless {
+    fn update_display(&mut self, _display: &Display) {}
+}
+
+impl AudioSink for Headless {
+    fn start_sound(&mut self) {}
+    fn stop_sound(&mut self) {}
+}
+
+impl InputSource for Headless {
+    fn check_inputs(&mut self, _keypad: &mut Keypad) -> EmulatorState {
+        EmulatorState::Playing
+    }
+}
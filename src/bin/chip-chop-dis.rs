@@ -0,0 +1,88 @@
+use std::{env, fs};
+use std::process::ExitCode;
+
+use chip_chop::{Platform, QuirkProfile};
+use chip_chop::args::Args;
+use chip_chop::config::DEFAULT_QUIRKS;
+use chip_chop::disassembler::{Disassembly, Syntax};
+
+const USAGE: &str = "\
+Usage: chip-chop-dis ROM [OPTIONS]
+
+Disassembles ROM, following jumps and calls from its entry point to tell code
+from data, and prints it to stdout.
+
+Options:
+  --syntax <SYNTAX>    octo (source Octo can assemble again) or classic (a
+                       listing of addresses, opcodes and Cowgod's mnemonics)
+                       (default: octo)
+  --platform <PLATFORM>
+                       the instruction set to decode: chip8, hires, chip8x,
+                       schip, xochip or megachip (default: picked from the
+                       ROM's extension and header, like chip-chop does)
+  -h, --help           print this help and exit";
+
+struct Options {
+    rom_path: String,
+    syntax: Syntax,
+    platform: Option<Platform>,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut rom_path = None;
+    let mut syntax = Syntax::Octo;
+    let mut platform = None;
+
+    let mut args = Args::new(args);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--syntax" => syntax = args.value("--syntax")?.parse()?,
+            "--platform" => platform = Some(args.value("--platform")?.parse()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if rom_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one ROM can be given", arg));
+                }
+                rom_path = Some(arg);
+            }
+        }
+    }
+
+    let rom_path = rom_path.ok_or("no ROM given")?;
+    Ok(Command::Run(Options { rom_path, syntax, platform }))
+}
+
+pub fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("error: could not read ROM '{}': {}", options.rom_path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // the same guesses chip-chop makes with its default settings
+    let profile = QuirkProfile::from_rom_path(&options.rom_path).unwrap_or(DEFAULT_QUIRKS);
+    let platform = options.platform.unwrap_or_else(|| Platform::for_rom(profile, &rom));
+    let disassembly = Disassembly::new(&rom, platform).with_quirks(profile.quirks());
+    print!("{}", disassembly.render(options.syntax));
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,186 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use log::LevelFilter;
+
+use chip_chop::{KeyWaitMode, Platform, QuirkProfile, TimingMode, UnknownOpcodePolicy};
+use chip_chop::args::Args;
+
+use crate::frontend::Config;
+
+pub const USAGE: &str = "\
+Usage: chip-chop [ROM] [OPTIONS]
+
+Plays ROM, or lets you pick one from the ROM folder when no ROM is given.
+
+Options:
+  --speed <SPEED>      game speed multiplier (default: 1.0)
+  --scale <SCALE>      window pixels per CHIP-8 pixel (default: 15)
+  --volume <VOLUME>    buzzer volume between 0 and 1 (default: 0.02)
+  --timing <MODE>      fixed runs cpu_hz instructions per second, vip charges
+                       each instruction its COSMAC VIP machine cycles
+                       (default: fixed)
+  --quirks <PROFILE>   force a quirk profile: modern, vip, chip8x, chip48,
+                       schip, xochip or megachip
+                       (default: picked from the ROM's extension, else
+                       modern)
+  --platform <PLATFORM>
+                       force an instruction set: chip8, hires, chip8x, schip,
+                       xochip or megachip (default: detected from the ROM's
+                       header, else the quirk profile's)
+  --unknown-opcodes <POLICY>
+                       what to do on an unknown opcode: ignore, warn, halt
+                       or machine-call (skip 0NNN, halt on the rest)
+                       (default: warn)
+  --key-wait <MODE>    when FX0A takes a key: release (pressed and let go,
+                       like the VIP) or press (default: release)
+  --log-level <LEVEL>  off, error, warn, info, debug or trace (default: warn)
+  --log-file <PATH>    write log messages and the trace to PATH instead of
+                       stderr
+  --debug              start in the debugger, stopped before the first
+                       instruction (F10 opens it while playing)
+  --trace              log every instruction with its PC, opcode, mnemonic
+                       and the registers it changed
+  --trace-range <START-END>
+                       only trace instructions in this address range,
+                       e.g. 0x200-0x2ff (implies --trace)
+  --config <PATH>      read settings from PATH instead of
+                       $XDG_CONFIG_HOME/chip-chop/config.toml
+  -h, --help           print this help and exit
+
+Options given here take precedence over the config file.";
+
+// anything left as None falls back to the config file
+#[derive(Default)]
+pub struct Options {
+    pub rom_path: Option<String>,
+    pub config_path: Option<String>,
+    pub speed: Option<f32>,
+    pub scale: Option<i32>,
+    pub volume: Option<f32>,
+    pub timing: Option<TimingMode>,
+    pub quirks: Option<QuirkProfile>,
+    pub platform: Option<Platform>,
+    pub unknown_opcodes: Option<UnknownOpcodePolicy>,
+    pub key_wait: Option<KeyWaitMode>,
+    pub log_level: Option<LevelFilter>,
+    pub log_file: Option<String>,
+    pub debug: bool,
+    pub trace: bool,
+    pub trace_range: Option<RangeInclusive<usize>>,
+}
+
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut options = Options::default();
+
+    let mut args = Args::new(args);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--speed" => options.speed = Some(parse_value("--speed", &args.value("--speed")?)?),
+            "--scale" => options.scale = Some(parse_value("--scale", &args.value("--scale")?)?),
+            "--volume" => options.volume = Some(parse_value("--volume", &args.value("--volume")?)?),
+            "--timing" => options.timing = Some(args.value("--timing")?.parse()?),
+            "--unknown-opcodes" => options.unknown_opcodes = Some(args.value("--unknown-opcodes")?.parse()?),
+            "--key-wait" => options.key_wait = Some(args.value("--key-wait")?.parse()?),
+            "--log-level" => options.log_level = Some(parse_value("--log-level", &args.value("--log-level")?)?),
+            "--log-file" => options.log_file = Some(args.value("--log-file")?),
+            "--debug" => options.debug = true,
+            "--trace" => options.trace = true,
+            "--trace-range" => {
+                options.trace_range = Some(parse_range(&args.value("--trace-range")?)?);
+                options.trace = true;
+            }
+            "--config" => options.config_path = Some(args.value("--config")?),
+            "--quirks" => options.quirks = Some(args.value("--quirks")?.parse()?),
+            "--platform" => options.platform = Some(args.value("--platform")?.parse()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if options.rom_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one ROM can be given", arg));
+                }
+                options.rom_path = Some(arg);
+            }
+        }
+    }
+
+    validate(&options)?;
+    Ok(Command::Run(options))
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+fn parse_address(value: &str) -> Option<usize> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_range(value: &str) -> Result<RangeInclusive<usize>, String> {
+    let invalid = || format!("invalid value '{}' for --trace-range, expected START-END like 0x200-0x2ff", value);
+
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = parse_address(start.trim()).ok_or_else(invalid)?;
+    let end = parse_address(end.trim()).ok_or_else(invalid)?;
+    if start > end {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
+
+fn validate(options: &Options) -> Result<(), String> {
+    if let Some(speed) = options.speed {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(format!("--speed must be a positive number, got {}", speed));
+        }
+    }
+    if let Some(scale) = options.scale {
+        if scale <= 0 {
+            return Err(format!("--scale must be a positive integer, got {}", scale));
+        }
+    }
+    if let Some(volume) = options.volume {
+        if !(0.0..=1.0).contains(&volume) {
+            return Err(format!("--volume must be between 0 and 1, got {}", volume));
+        }
+    }
+    if let Some(rom_path) = &options.rom_path {
+        if !Path::new(rom_path).is_file() {
+            return Err(format!("ROM '{}' does not exist", rom_path));
+        }
+    }
+    Ok(())
+}
+
+impl Options {
+    /// Applies the settings given on the command line on top of `config`.
+    pub fn override_config(&self, config: &mut Config) {
+        if let Some(speed) = self.speed {
+            config.speed = speed;
+        }
+        if let Some(scale) = self.scale {
+            config.scale = scale;
+        }
+        if let Some(volume) = self.volume {
+            config.volume = volume;
+        }
+        if let Some(timing) = self.timing {
+            config.timing = timing;
+        }
+        if let Some(policy) = self.unknown_opcodes {
+            config.unknown_opcodes = policy;
+        }
+        if let Some(mode) = self.key_wait {
+            config.key_wait = mode;
+        }
+        config.trace_range = self.trace_range.clone();
+    }
+}
@@ -0,0 +1,45 @@
+// pitch 64 plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+pub const PATTERN_SIZE: usize = 16;
+
+/// The 1-bit XO-CHIP sound loaded by F002, and the FX3A pitch it plays at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioPattern {
+    /// 128 samples, most significant bit first.
+    pub bits: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    /// Samples per second, `4000 * 2^((pitch - 64) / 48)`.
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// The sample at `position`, wrapping around the end of the pattern.
+    pub fn bit(&self, position: usize) -> bool {
+        let position = position % (PATTERN_SIZE * 8);
+        self.bits[position / 8] & (0x80 >> (position % 8)) != 0
+    }
+}
+
+// 060N sounds start with a 16-bit sample rate, a 24-bit length and a reserved byte
+pub const SAMPLE_HEADER_SIZE: usize = 6;
+
+/// A MEGA-CHIP digitised sound started by 060N.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Samples per second.
+    pub rate: u16,
+    /// Unsigned 8-bit samples, 0x80 is silence.
+    pub data: Vec<u8>,
+    /// 0600 loops the sound until 0700, 0601 plays it once.
+    pub looping: bool,
+}
+
+/// What the CPU asked the audio device to do with digitised sound since it was last asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleCommand {
+    Play(Sample),
+    Stop,
+}
@@ -1,15 +1,33 @@
-use std::fs::File;
-use std::io::{self, Read};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
 
+use log::{debug, info, log_enabled, trace, warn, Level};
 use rand::Rng;
 
-use crate::components::{Display, Keypad, Sound};
-use crate::config::{FONT_LOAD_START, ROM_LOAD_START, USE_NEW, USE_NEW_LOAD};
+use crate::components::{
+    AudioPattern, BlendMode, Display, Instruction, Keypad, Sample, SampleCommand, DEFAULT_PITCH, PATTERN_SIZE,
+    SAMPLE_HEADER_SIZE,
+};
+use crate::config::{FONT_LOAD_START, KEY_WAIT, ROM_LOAD_START, STACK_DEPTH, UNKNOWN_OPCODES};
+use crate::error::{Chip8Error, CpuFault, FaultKind};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::savestate::{self, SaveStateError, StateReader, StateWriter};
+use crate::timing;
+
+/// Log target of the per-instruction trace, kept apart from the rest of the
+/// CPU's logging so it can be switched on by itself.
+pub const TRACE_TARGET: &str = "chip_chop::trace";
 
-const MEMORY_SIZE: usize = 4096;
 const REGISTERS_SIZE: usize = 16;
+// MEGA-CHIP sprite sides set by 03NN/04NN, where 00 means 256
+const MAX_SPRITE_SIZE: usize = 256;
 
-const FONTS: [u8; 80] = [
+pub const FONTS: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -28,84 +46,312 @@ const FONTS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// 8x10 SUPER-CHIP digits for FX30, loaded right after FONTS
+pub const BIG_FONTS: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// persistent "RPL user flags" of the HP-48, saved and restored by FX75/FX85
+const RPL_FLAGS_SIZE: usize = 16;
+
+/// What the CPU does when it decodes an opcode it doesn't know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    /// Skip it silently.
+    Ignore,
+    /// Skip it, printing a warning the first time each opcode is seen.
+    Warn,
+    /// Halt with a `FaultKind::UnknownOpcode` fault.
+    Halt,
+    /// Treat 0NNN as a call to 1802 machine code and skip it; halt on anything else.
+    MachineCall,
+}
+
+impl fmt::Display for UnknownOpcodePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnknownOpcodePolicy::Ignore => "ignore",
+            UnknownOpcodePolicy::Warn => "warn",
+            UnknownOpcodePolicy::Halt => "halt",
+            UnknownOpcodePolicy::MachineCall => "machine-call",
+        })
+    }
+}
+
+impl FromStr for UnknownOpcodePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "ignore" => Ok(UnknownOpcodePolicy::Ignore),
+            "warn" => Ok(UnknownOpcodePolicy::Warn),
+            "halt" => Ok(UnknownOpcodePolicy::Halt),
+            "machine-call" | "machinecall" => Ok(UnknownOpcodePolicy::MachineCall),
+            _ => Err(format!("unknown opcode policy '{}' (expected one of ignore, warn, halt, machine-call)", s)),
+        }
+    }
+}
+
+/// When FX0A considers a key entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWaitMode {
+    /// Once the key is pressed and released again, like the COSMAC VIP.
+    Release,
+    /// As soon as a key is down, which games may then see still held.
+    Press,
+}
+
+impl fmt::Display for KeyWaitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyWaitMode::Release => "release",
+            KeyWaitMode::Press => "press",
+        })
+    }
+}
+
+impl FromStr for KeyWaitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "release" => Ok(KeyWaitMode::Release),
+            "press" => Ok(KeyWaitMode::Press),
+            _ => Err(format!("unknown key wait mode '{}' (expected release or press)", s)),
+        }
+    }
+}
+
+// progress of an FX0A that hasn't completed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    Waiting,
+    // pressed, waiting for this key to be released
+    Held(usize),
+}
+
 pub struct Cpu {
     pub display: Display,
     pub keypad: Keypad,
-    sound: Sound,
-    memory: [u8; MEMORY_SIZE],
+    /// CHIP-8X's second keypad, read by EXF2 and EXF5.
+    pub second_keypad: Keypad,
+    platform: Platform,
+    quirks: Quirks,
+    font_start: usize,
+    memory: Vec<u8>,
+    // one past the highest byte the fonts, the ROM or the program ever wrote; all zero above it
+    memory_end: usize,
     registers: [u8; REGISTERS_SIZE],
+    rpl_flags: [u8; RPL_FLAGS_SIZE],
     pc: usize,
     i: usize,
     stack: Vec<usize>,
+    stack_depth: usize,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    key_wait_mode: KeyWaitMode,
+    key_wait: KeyWait,
+    // every unknown opcode seen so far and how many times it was executed
+    unknown_opcodes: BTreeMap<u16, usize>,
+    // only instructions at these addresses are traced, all of them if None
+    trace_range: Option<RangeInclusive<usize>>,
     delay_timer: u8,
     sound_timer: u8,
+    // XO-CHIP sound loaded by F002, the buzzer plays until then
+    audio_pattern: Option<[u8; PATTERN_SIZE]>,
+    pitch: u8,
+    // MEGA-CHIP sprite size (03NN/04NN), blending (080N) and collision colour (09NN)
+    sprite_width: usize,
+    sprite_height: usize,
+    blend_mode: BlendMode,
+    collision_colour: u8,
+    // digitised sound started or stopped since the frontend last took it
+    sample_command: Option<SampleCommand>,
+    // CHIP-8X I/O port: the last byte FXF8 sent and the byte FXFB reads
+    io_output: u8,
+    io_input: u8,
+    // identifies the loaded ROM in save states
+    rom_hash: u64,
+    // COSMAC VIP machine cycles the last instruction took
+    last_cycles: u32,
+    // what the last instruction wrote, for watchpoints
+    memory_write: Option<Range<usize>>,
+    index_write: bool,
+    // set by DXYN under the display wait quirk until the next timer tick
+    vblank_wait: bool,
+    // once set, the CPU refuses to run until it is reset
+    fault: Option<CpuFault>,
+    // set by 00FD
+    exited: bool,
 }
 
 impl Cpu {
-    pub fn new(display: Display, keypad: Keypad, sound: Sound) -> Self {
-        let memory: [u8; MEMORY_SIZE] = [0; MEMORY_SIZE];
+    pub fn new(quirks: Quirks) -> Self {
+        let display: Display = Display::new();
+        let keypad: Keypad = Keypad::new();
+        let memory: Vec<u8> = vec![0; Platform::Chip8.memory_size()];
         let registers: [u8; REGISTERS_SIZE] = [0; REGISTERS_SIZE];
         let pc: usize = ROM_LOAD_START;
         let i: usize = 0;
         let stack: Vec<usize> = Vec::new();
         let delay_timer: u8 = 0;
         let sound_timer: u8 = 0;
+        let vblank_wait: bool = false;
 
         Cpu { 
             display, 
             keypad, 
-            sound, 
+            second_keypad: Keypad::new(),
+            platform: Platform::Chip8,
+            quirks,
+            font_start: FONT_LOAD_START,
             memory, 
             registers, 
+            rpl_flags: [0; RPL_FLAGS_SIZE],
             pc, 
             i, 
             stack, 
+            stack_depth: STACK_DEPTH,
+            unknown_opcode_policy: UNKNOWN_OPCODES,
+            key_wait_mode: KEY_WAIT,
+            key_wait: KeyWait::Idle,
+            unknown_opcodes: BTreeMap::new(),
+            trace_range: None,
             delay_timer, 
             sound_timer,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            sprite_width: 0,
+            sprite_height: 0,
+            blend_mode: BlendMode::Normal,
+            collision_colour: 0,
+            sample_command: None,
+            io_output: 0,
+            io_input: 0,
+            rom_hash: 0,
+            last_cycles: 0,
+            memory_write: None,
+            memory_end: 0,
+            index_write: false,
+            vblank_wait,
+            fault: None,
+            exited: false,
         }
     }
 
-    pub fn init_load(&mut self, rom_path: &str) {
+    /// Selects the instruction set; the default is plain CHIP-8.
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self.memory = vec![0; platform.memory_size()];
+        self.memory_end = 0;
+
+        let (width, height) = platform.screen_size();
+        self.display.resize(width, height);
+        self.display.set_colour_zones(platform.chip8x());
+        self.pc = platform.rom_start();
+        self
+    }
+
+    /// Moves the built-in font away from `FONT_LOAD_START`; call before loading a ROM.
+    pub fn with_font_start(mut self, font_start: usize) -> Self {
+        self.font_start = font_start;
+        self
+    }
+
+    /// Overrides the default `STACK_DEPTH` limit on nested calls.
+    pub fn with_stack_depth(mut self, stack_depth: usize) -> Self {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    /// Overrides the default `UNKNOWN_OPCODES` policy.
+    pub fn with_unknown_opcode_policy(mut self, policy: UnknownOpcodePolicy) -> Self {
+        self.unknown_opcode_policy = policy;
+        self
+    }
+
+    /// Whether FX0A completes on key release (the default) or on key press.
+    pub fn with_key_wait_mode(mut self, mode: KeyWaitMode) -> Self {
+        self.key_wait_mode = mode;
+        self
+    }
+
+    /// Restricts the instruction trace to instructions whose address lies in `range`.
+    pub fn with_trace_range(mut self, range: RangeInclusive<usize>) -> Self {
+        self.trace_range = Some(range);
+        self
+    }
+
+    pub fn init_load(&mut self, rom_path: &str) -> Result<(), Chip8Error> {
+        let rom_data = Cpu::read_rom_from_file(rom_path)?;
+        self.init_load_bytes(&rom_data)
+    }
+
+    /// Loads the fonts and a ROM that is already in memory, e.g. one bundled with a test.
+    pub fn init_load_bytes(&mut self, rom_data: &[u8]) -> Result<(), Chip8Error> {
         self.load_fonts();
-        self.load_rom(rom_path);
+        self.load_rom(rom_data)
     }
 
     fn load_fonts(&mut self) {
-        let start = FONT_LOAD_START;
-        let end = FONT_LOAD_START + FONTS.len();
+        let start = self.font_start;
+        let end = self.font_start + FONTS.len();
         for (i, byte) in (start..end).zip(FONTS) {
             self.memory[i] = byte;
         }
 
-        println!("Loaded fonts to memory address {:#06x}", FONT_LOAD_START);
-    }
+        let big_end = end + BIG_FONTS.len();
+        self.memory[end..big_end].copy_from_slice(&BIG_FONTS);
+        self.memory_end = self.memory_end.max(big_end);
 
-    fn load_rom(&mut self, rom_path: &str) {
-        match Cpu::read_rom_from_file(rom_path) {
-            Ok(rom_data) => {
-                for i in 0..rom_data.len() {
-                    self.memory[ROM_LOAD_START + i] = rom_data[i];
-                }
+        debug!("Loaded fonts to memory address {:#06x}", self.font_start);
+    }
 
-                println!("Sucessfully read ROM starting at memory address {:#06x}", ROM_LOAD_START);
-                println!("ROM size: {} bytes", rom_data.len());
-                println!("First bytes: {:?}", &rom_data[..16]);
-            }
-            Err(err) => {
-                eprintln!("Error reading ROM file: {}", err);
-            }
+    fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), Chip8Error> {
+        let start = self.platform.rom_start();
+        let max = self.memory.len() - start;
+        if rom_data.is_empty() {
+            return Err(Chip8Error::EmptyRom);
+        }
+        if rom_data.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: rom_data.len(), max });
         }
-    }
 
-    fn read_rom_from_file(file_path: &str) -> io::Result<Vec<u8>> {
-        let mut file = File::open(file_path)?;
+        self.memory[start..start + rom_data.len()].copy_from_slice(rom_data);
+        self.memory_end = self.memory_end.max(start + rom_data.len());
+        self.rom_hash = savestate::rom_hash(rom_data);
 
-        let mut rom_data = Vec::new();
-        file.read_to_end(&mut rom_data)?;
+        info!("Sucessfully read ROM starting at memory address {:#06x}", start);
+        info!("ROM size: {} bytes", rom_data.len());
+        debug!("First bytes: {:?}", &rom_data[..rom_data.len().min(16)]);
+        Ok(())
+    }
 
-        Ok(rom_data)
+    fn read_rom_from_file(file_path: &str) -> Result<Vec<u8>, Chip8Error> {
+        fs::read(file_path).map_err(|source| match source.kind() {
+            io::ErrorKind::NotFound => Chip8Error::MissingRom { path: file_path.to_string() },
+            _ => Chip8Error::UnreadableRom { path: file_path.to_string(), source },
+        })
     }
 
+    /// Counts both timers down by one; call at 60 Hz. This is also the vertical
+    /// blank that releases a DXYN stalled by the display wait quirk, so a CPU
+    /// that is cycled without it draws one sprite and then stops.
     pub fn update_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
@@ -113,171 +359,651 @@ impl Cpu {
 
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
+        }
+
+        self.vblank_wait = false;
+    }
+
+    /// True while the sound timer is running, i.e. while the buzzer should be audible.
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The XO-CHIP sound to play while `sound_active`, or None for the classic buzzer.
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        self.audio_pattern.map(|bits| AudioPattern { bits, pitch: self.pitch })
+    }
+
+    /// Takes the MEGA-CHIP sample the ROM started or stopped since the last call, if any.
+    pub fn take_sample_command(&mut self) -> Option<SampleCommand> {
+        self.sample_command.take()
+    }
+
+    /// The last byte a CHIP-8X ROM wrote to its I/O port with FXF8.
+    pub fn io_output(&self) -> u8 {
+        self.io_output
+    }
+
+    /// Sets the byte a CHIP-8X ROM reads from its I/O port with FXFB.
+    pub fn set_io_input(&mut self, value: u8) {
+        self.io_input = value;
+    }
+
+    /// Snapshots the whole machine in the versioned save state format.
+    ///
+    /// Settings that come from the frontend rather than the ROM (stack depth,
+    /// unknown opcode policy, key wait mode, tracing) are not part of it.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_hash);
+
+        let platform = Platform::ALL.iter().position(|&platform| platform == self.platform).unwrap_or(0);
+        writer.u8(platform as u8);
+        let quirks = self.quirks;
+        for quirk in [quirks.shift, quirks.jump, quirks.load_store, quirks.vf_reset, quirks.clip, quirks.display_wait] {
+            writer.bool(quirk);
+        }
+
+        writer.usize(self.font_start);
+        // only up to the highest byte ever written, so an untouched MEGA-CHIP doesn't put 16 MiB of zeros in every state
+        writer.bytes(&self.memory[..self.memory_end]);
+        writer.bytes(&self.registers);
+        writer.bytes(&self.rpl_flags);
+        writer.usize(self.pc);
+        writer.usize(self.i);
+        writer.usize(self.stack.len());
+        self.stack.iter().for_each(|&address| writer.usize(address));
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.bool(self.vblank_wait);
+        writer.bool(self.exited);
+        match self.key_wait {
+            KeyWait::Idle => writer.u8(0),
+            KeyWait::Waiting => writer.u8(1),
+            KeyWait::Held(key) => writer.u8(2 + key as u8),
+        }
+
+        writer.bytes(self.audio_pattern.as_ref().map_or(&[], |bits| &bits[..]));
+        writer.u8(self.pitch);
+        writer.usize(self.sprite_width);
+        writer.usize(self.sprite_height);
+        writer.u8(self.blend_mode.mode());
+        writer.u8(self.collision_colour);
+        writer.u8(self.io_output);
+        writer.u8(self.io_input);
+
+        self.display.write_state(&mut writer);
+        self.keypad.write_state(&mut writer);
+        self.second_keypad.write_state(&mut writer);
+        writer.finish()
+    }
+
+    /// Restores a snapshot from `save_state`, leaving the machine untouched if
+    /// it is unreadable or was taken with a different ROM loaded.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state, self.rom_hash)?;
+
+        let platform = *Platform::ALL.get(reader.u8()? as usize).ok_or(SaveStateError::Corrupt)?;
+        let quirks = Quirks {
+            shift: reader.bool()?,
+            jump: reader.bool()?,
+            load_store: reader.bool()?,
+            vf_reset: reader.bool()?,
+            clip: reader.bool()?,
+            display_wait: reader.bool()?,
+        };
+
+        let font_start = reader.usize()?;
+        let used_memory = reader.bytes()?;
+        if used_memory.len() > platform.memory_size() {
+            return Err(SaveStateError::Corrupt);
+        }
+        let mut memory = vec![0; platform.memory_size()];
+        memory[..used_memory.len()].copy_from_slice(used_memory);
+        let mut registers = [0; REGISTERS_SIZE];
+        registers.copy_from_slice(reader.exact_bytes(REGISTERS_SIZE)?);
+        let mut rpl_flags = [0; RPL_FLAGS_SIZE];
+        rpl_flags.copy_from_slice(reader.exact_bytes(RPL_FLAGS_SIZE)?);
+        let pc = reader.usize()?;
+        let i = reader.usize()?;
+        let stack_len = reader.usize()?;
+        if stack_len > self.stack_depth {
+            return Err(SaveStateError::Corrupt);
+        }
+        let stack = (0..stack_len).map(|_| reader.usize()).collect::<Result<Vec<usize>, _>>()?;
+        // I and PC may legitimately point past memory, every access checks them; the fonts can't
+        if font_start >= platform.memory_size() {
+            return Err(SaveStateError::Corrupt);
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let vblank_wait = reader.bool()?;
+        let exited = reader.bool()?;
+        let key_wait = match reader.u8()? {
+            0 => KeyWait::Idle,
+            1 => KeyWait::Waiting,
+            key @ 2..=0x11 => KeyWait::Held((key - 2) as usize),
+            _ => return Err(SaveStateError::Corrupt),
+        };
+
+        let audio_pattern = match reader.bytes()? {
+            [] => None,
+            bits => Some(bits.try_into().map_err(|_| SaveStateError::Corrupt)?),
+        };
+        let pitch = reader.u8()?;
+        let sprite_width = reader.usize()?;
+        let sprite_height = reader.usize()?;
+        if sprite_width > MAX_SPRITE_SIZE || sprite_height > MAX_SPRITE_SIZE {
+            return Err(SaveStateError::Corrupt);
+        }
+        let blend_mode = BlendMode::from_mode(reader.u8()?).ok_or(SaveStateError::Corrupt)?;
+        let collision_colour = reader.u8()?;
+        let io_output = reader.u8()?;
+        let io_input = reader.u8()?;
+
+        let display = Display::read_state(&mut reader, platform)?;
+        let keypad = Keypad::read_state(&mut reader)?;
+        let second_keypad = Keypad::read_state(&mut reader)?;
+        reader.finish()?;
+
+        self.platform = platform;
+        self.quirks = quirks;
+        self.font_start = font_start;
+        self.memory = memory;
+        self.memory_end = used_memory.len();
+        self.registers = registers;
+        self.rpl_flags = rpl_flags;
+        self.pc = pc;
+        self.i = i;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.vblank_wait = vblank_wait;
+        self.exited = exited;
+        self.key_wait = key_wait;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.sprite_width = sprite_width;
+        self.sprite_height = sprite_height;
+        self.blend_mode = blend_mode;
+        self.collision_colour = collision_colour;
+        self.io_output = io_output;
+        self.io_input = io_input;
+        self.display = display;
+        self.keypad = keypad;
+        self.second_keypad = second_keypad;
+        // a restored machine gets another chance, whatever stopped it before
+        self.fault = None;
+        Ok(())
+    }
+
+    /// Machine cycles the last instruction would have taken on a COSMAC VIP.
+    pub fn last_cycles(&self) -> u32 {
+        self.last_cycles
+    }
+
+    /// The memory the last instruction wrote to, if any, even if the bytes didn't change.
+    pub fn last_memory_write(&self) -> Option<Range<usize>> {
+        self.memory_write.clone()
+    }
+
+    /// True if the last instruction set I, even to the value it already had.
+    pub fn wrote_index(&self) -> bool {
+        self.index_write
+    }
+
+    /// True while FX0A is stalling the CPU until a key is entered.
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    /// True while DXYN is waiting for the next timer tick under the display wait quirk.
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Overwrites VX from outside the program, e.g. from a debugger.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.registers[x] = value;
+    }
+
+    pub fn set_i(&mut self, i: usize) {
+        self.i = i;
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// True once the ROM has run 00FD.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn unknown_opcodes(&self) -> &BTreeMap<u16, usize> {
+        &self.unknown_opcodes
+    }
+
+    /// The fault that halted the CPU, if any.
+    pub fn fault(&self) -> Option<CpuFault> {
+        self.fault
+    }
+
+    /// Runs one instruction. After a fault the CPU stays halted and keeps returning the same fault.
+    pub fn cycle(&mut self) -> Result<(), CpuFault> {
+        self.memory_write = None;
+        self.index_write = false;
+
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+        if self.vblank_wait || self.exited {
+            return Ok(());
+        }
 
-            if self.sound_timer == 0 {
-                self.sound.stop_sound();
+        let pc = self.pc;
+        let before = self.tracing(pc).then_some((self.registers, self.i));
+
+        let result = match self.fetch() {
+            Ok(instr) => {
+                let result = self.decode_execute(instr).map_err(|kind| CpuFault { kind, pc, opcode: Some(instr) });
+                if let Some((registers, i)) = before {
+                    self.trace_instruction(pc, instr, &registers, i);
+                }
+                result
             }
+            Err(kind) => Err(CpuFault { kind, pc, opcode: None }),
+        };
+
+        if let Some(written) = &self.memory_write {
+            self.memory_end = self.memory_end.max(written.end);
+        }
+
+        if let Err(fault) = result {
+            // leave PC on the faulting instruction so it can be inspected
+            self.pc = pc;
+            self.fault = Some(fault);
         }
+        result
+    }
+
+    fn tracing(&self, pc: usize) -> bool {
+        log_enabled!(target: TRACE_TARGET, Level::Trace)
+            && self.trace_range.as_ref().is_none_or(|range| range.contains(&pc))
     }
 
-    pub fn cycle(&mut self) {
-        let instr = self.fetch();
-        self.decode_execute(instr);
+    // one line per instruction: address, opcode, mnemonic and whatever registers it changed
+    fn trace_instruction(&self, pc: usize, instr: u16, registers: &[u8; REGISTERS_SIZE], i: usize) {
+        let mnemonic = match Instruction::decode(instr, self.platform) {
+            Some(instruction) => instruction.mnemonic(self.quirks),
+            None => String::from("???"),
+        };
+
+        let mut deltas: Vec<String> = Vec::new();
+        for (reg, (old, new)) in registers.iter().zip(self.registers.iter()).enumerate() {
+            if old != new {
+                deltas.push(format!("V{:X}: {:#04x} -> {:#04x}", reg, old, new));
+            }
+        }
+        if i != self.i {
+            deltas.push(format!("I: {:#05x} -> {:#05x}", i, self.i));
+        }
+
+        let line = format!("{:#06x}  {:04x}  {:<20} {}", pc, instr, mnemonic, deltas.join(", "));
+        trace!(target: TRACE_TARGET, "{}", line.trim_end());
     }
 
-    fn fetch(&mut self) -> u16 {
+    fn fetch(&mut self) -> Result<u16, FaultKind> {
+        if self.pc.saturating_add(1) >= self.memory.len() {
+            return Err(FaultKind::PcOutOfRange);
+        }
+
         let i1: u8 = self.memory[self.pc];
         let i2: u8 = self.memory[self.pc + 1];
 
         self.pc += 2;
 
-        ((i1 as u16) << 8) + i2 as u16
+        Ok(((i1 as u16) << 8) + i2 as u16)
     }
 
-    fn decode_execute(&mut self, instr: u16) {
-        // x and y are used for register lookup
-        let x: usize = ((instr & 0x0F00) >> 8) as usize;
-        let y: usize = ((instr & 0x00F0) >> 4) as usize;
+    /// Checks that the `len` bytes starting at I are addressable.
+    fn check_index(&self, len: usize) -> Result<(), FaultKind> {
+        if self.i.saturating_add(len) > self.memory.len() {
+            return Err(FaultKind::IndexOutOfRange { i: self.i });
+        }
+        Ok(())
+    }
 
-        let vx: u8 = self.registers[x];
-        let vy: u8 = self.registers[y];
+    fn decode_execute(&mut self, instr: u16) -> Result<(), FaultKind> {
+        match Instruction::decode(instr, self.platform) {
+            Some(instruction) => {
+                let (registers, next) = (self.registers, self.pc);
+                self.execute(instruction)?;
+                self.last_cycles = timing::vip_cycles(instruction, &registers, self.pc != next);
+                Ok(())
+            }
+            None => {
+                self.last_cycles = 0;
+                self.unknown_opcode(instr)
+            }
+        }
+    }
 
-        let n: u8 = (instr & 0x000F) as u8;
-        let nn: u8 = (instr & 0x00FF) as u8;
-        let nnn: usize = (instr & 0x0FFF) as usize; // used as 12-bit memory address
-        
-        let mut known: bool = true;
-        match (instr & 0xF000) >> 12 {
-            0x0 => {
-                match nnn {
-                    0x0E0 => self.display.clear(),
-                    0x0EE => self.pop_subroutine(),
-                    _ => { known = false; }
+    fn execute(&mut self, instruction: Instruction) -> Result<(), FaultKind> {
+        match instruction {
+            Instruction::Clear => self.display.clear(),
+            Instruction::Return => self.pop_subroutine()?,
+            Instruction::Jump { nnn } => self.jump(nnn),
+            Instruction::Call { nnn } => self.push_subroutine(nnn)?,
+            Instruction::SkipEqImm { x, nn } => self.skip_if_equal(self.registers[x], nn),
+            Instruction::SkipNeImm { x, nn } => self.skip_if_not_equal(self.registers[x], nn),
+            Instruction::SkipEq { x, y } => self.skip_if_equal(self.registers[x], self.registers[y]),
+            Instruction::LoadImm { x, nn } => self.register_set(x, nn),
+            Instruction::AddImm { x, nn } => self.register_set(x, self.registers[x].wrapping_add(nn)),
+            Instruction::Move { x, y } => self.register_set(x, self.registers[y]),
+            Instruction::Or { x, y } => self.logical_set(x, self.registers[x] | self.registers[y]),
+            Instruction::And { x, y } => self.logical_set(x, self.registers[x] & self.registers[y]),
+            Instruction::Xor { x, y } => self.logical_set(x, self.registers[x] ^ self.registers[y]),
+            Instruction::Add { x, y } => self.register_add(x, self.registers[x] as u32, self.registers[y] as u32),
+            Instruction::Sub { x, y } => self.register_sub(x, self.registers[x], self.registers[y]),
+            Instruction::ShiftRight { x, y } => {
+                if self.quirks.shift {
+                    self.shift_right(x, self.registers[x]);
+                }
+                else {
+                    self.shift_right(x, self.registers[y]);
                 }
             },
-            0x1 => self.jump(nnn),
-            0x2 => self.push_subroutine(nnn),
-            0x3 => self.skip_if_equal(vx, nn),
-            0x4 => self.skip_if_not_equal(vx, nn),
-            0x5 => self.skip_if_equal(vx, vy),
-            0x6 => self.register_set(x, nn),
-            0x7 => self.register_set(x, vx.wrapping_add(nn)),
-            0x8 => {
-
-                match n {
-                    0x0 => self.register_set(x, vy),
-                    0x1 => self.register_set(x, vx | vy),
-                    0x2 => self.register_set(x, vx & vy),
-                    0x3 => self.register_set(x, vx ^ vy),
-                    0x4 => self.register_add(x, vx as u32, vy as u32),
-                    0x5 => self.register_sub(x, vx, vy),
-                    0x6 => {
-                        if USE_NEW {
-                            self.register_set(x, vy);
-                        }
-                        self.shift_right(x);
-                    },
-                    0x7 => self.register_sub(x, vy, vx),
-                    0xE => {
-                        if USE_NEW {
-                            self.register_set(x, vy);
-                        }
-                        self.shift_left(x);
-                    },
-                    _ => { known = false; }
+            Instruction::SubReverse { x, y } => self.register_sub(x, self.registers[y], self.registers[x]),
+            Instruction::ShiftLeft { x, y } => {
+                if self.quirks.shift {
+                    self.shift_left(x, self.registers[x]);
                 }
-            }
-            0x9 => self.skip_if_not_equal(vx, vy),
-            0xa => self.index_set(nnn),
-            0xb => {
-                if USE_NEW {
-                    self.jump(nnn + vx as usize);
+                else {
+                    self.shift_left(x, self.registers[y]);
+                }
+            },
+            Instruction::SkipNe { x, y } => self.skip_if_not_equal(self.registers[x], self.registers[y]),
+            Instruction::LoadIndex { nnn } => self.index_set(nnn),
+            Instruction::JumpOffset { x, nnn } => {
+                if self.quirks.jump {
+                    self.jump(nnn + self.registers[x] as usize);
                 }
                 else {
                     self.jump(nnn + self.registers[0x0] as usize);
                 }
             },
-            0xc => self.set_random(x, nn),
-            0xd => self.draw(x, y, n),
-            0xe => {
-                match nn {
-                    0x9E => self.skip_if_pressed(vx as usize),
-                    0xA1 => self.skip_if_not_pressed(vx as usize),
-                    _ => { known = false; }
+            Instruction::Random { x, nn } => self.set_random(x, nn),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n)?,
+            Instruction::SkipKey { x } => self.skip_if_pressed(self.registers[x] as usize),
+            Instruction::SkipNotKey { x } => self.skip_if_not_pressed(self.registers[x] as usize),
+            Instruction::LoadDelay { x } => self.register_set(x, self.delay_timer),
+            Instruction::WaitKey { x } => self.wait_for_key(x),
+            Instruction::SetDelay { x } => self.set_delay_timer(self.registers[x]),
+            Instruction::SetSound { x } => self.set_sound_timer(self.registers[x]),
+            Instruction::AddIndex { x } => self.index_set(self.i.saturating_add(self.registers[x] as usize)),
+            Instruction::LoadFont { x } => self.index_set(self.font_start + (self.registers[x] & 0xF) as usize * 5),
+            Instruction::StoreBcd { x } => self.store_decimal_digits(self.registers[x])?,
+            Instruction::Store { x } => {
+                self.load_memory_from_registers(x)?;
+                if self.quirks.load_store {
+                    self.index_set(self.i + x + 1);
+                }
+            },
+            Instruction::Load { x } => {
+                self.load_registers_from_memory(x)?;
+                if self.quirks.load_store {
+                    self.index_set(self.i + x + 1);
+                }
+            },
+            Instruction::ScrollDown { n } => self.display.scroll_down(n as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => self.exited = true,
+            Instruction::Lores => self.display.set_hires(false),
+            Instruction::Hires => self.display.set_hires(true),
+            Instruction::LoadBigFont { x } => {
+                let big_font_start = self.font_start + FONTS.len();
+                self.index_set(big_font_start + (self.registers[x] & 0xF) as usize * 10)
+            },
+            Instruction::StoreFlags { x } => self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]),
+            Instruction::LoadFlags { x } => self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]),
+            Instruction::ScrollUp { n } => self.display.scroll_up(n as usize),
+            Instruction::SaveRange { x, y } => self.save_register_range(x, y)?,
+            Instruction::LoadRange { x, y } => self.load_register_range(x, y)?,
+            Instruction::LongIndex => self.long_index()?,
+            Instruction::Plane { n } => self.display.select_planes(n),
+            Instruction::Audio => self.load_audio_pattern()?,
+            Instruction::Pitch { x } => self.pitch = self.registers[x],
+            Instruction::MegaOff => self.display.set_megachip(false),
+            Instruction::MegaOn => self.display.set_megachip(true),
+            Instruction::LongIndexHigh { nn } => self.long_index_high(nn)?,
+            Instruction::LoadPalette { nn } => self.load_palette(nn as usize)?,
+            // a size of 0 means 256
+            Instruction::SpriteWidth { nn } => self.sprite_width = if nn == 0 { MAX_SPRITE_SIZE } else { nn as usize },
+            Instruction::SpriteHeight { nn } => self.sprite_height = if nn == 0 { MAX_SPRITE_SIZE } else { nn as usize },
+            Instruction::ScreenAlpha { nn } => self.display.set_alpha(nn),
+            Instruction::PlaySample { n } => self.play_sample(n == 0)?,
+            Instruction::StopSample => self.sample_command = Some(SampleCommand::Stop),
+            Instruction::SetBlend { n } => self.blend_mode = BlendMode::from_mode(n).unwrap_or(BlendMode::Normal),
+            Instruction::CollisionColour { nn } => self.collision_colour = nn,
+            Instruction::CycleBackground => self.display.cycle_background(),
+            Instruction::AddNibbles { x, y } => self.add_nibbles(x, self.registers[x], self.registers[y]),
+            Instruction::ZoneColour { x, y, n } => self.set_zone_colour(x, y, n),
+            Instruction::SkipKey2 { x } => {
+                if self.second_keypad.is_pressed(self.registers[x] as usize & 0xF) {
+                    self.skip();
                 }
             }
-            0xf => {
-                match nn {
-                    0x07 => self.register_set(x, self.delay_timer),
-                    0x0A => self.wait_for_key(x),
-                    0x15 => self.set_delay_timer(vx),
-                    0x18 => self.set_sound_timer(vx),
-                    0x1E => self.index_set(self.i + vx as usize),
-                    0x29 => self.index_set(FONT_LOAD_START + (vx * 5) as usize),
-                    0x33 => self.store_decimal_digits(vx),
-                    0x55 => {
-                        if !USE_NEW_LOAD {
-                            self.i += x + 1;
-                        }
-                        self.load_memory_from_registers(x);
-                    },
-                    0x65 => {
-                        if !USE_NEW_LOAD {
-                            self.i += x + 1;
-                        }
-                        self.load_registers_from_memory(x);
-                    },
-                    _ => { known = false; }
+            Instruction::SkipNotKey2 { x } => {
+                if !self.second_keypad.is_pressed(self.registers[x] as usize & 0xF) {
+                    self.skip();
                 }
             }
-            _ => { known = false; }
+            Instruction::OutputPort { x } => self.io_output = self.registers[x],
+            Instruction::InputPort { x } => self.register_set(x, self.io_input),
         }
+        Ok(())
+    }
+
+    fn unknown_opcode(&mut self, instr: u16) -> Result<(), FaultKind> {
+        let count = self.unknown_opcodes.entry(instr).or_insert(0);
+        *count += 1;
+        let first_seen = *count == 1;
 
-        if !known {
-            println!("Unknown Command! {:#06x}", instr)
+        match self.unknown_opcode_policy {
+            UnknownOpcodePolicy::Ignore => Ok(()),
+            UnknownOpcodePolicy::Warn => {
+                if first_seen {
+                    warn!("Unknown opcode {:#06x} at {:#06x}, skipping", instr, self.pc - 2);
+                }
+                Ok(())
+            }
+            UnknownOpcodePolicy::Halt => Err(FaultKind::UnknownOpcode),
+            UnknownOpcodePolicy::MachineCall => {
+                if instr & 0xF000 == 0 {
+                    return Ok(());
+                }
+                Err(FaultKind::UnknownOpcode)
+            }
         }
     }
 
-    fn draw(&mut self, x: usize, y: usize, height: u8) {
-        let x_coord = self.registers[x] as usize;
-        let y_coord = self.registers[y] as usize;
+    fn draw(&mut self, x: usize, y: usize, height: u8) -> Result<(), FaultKind> {
+        if self.display.megachip() {
+            return self.draw_indexed(x, y);
+        }
+
+        let width = self.display.width();
+        let display_height = self.display.height();
+
+        // the starting coordinate always wraps, only the sprite body is clipped
+        let x_coord = self.registers[x] as usize % width;
+        let y_coord = self.registers[y] as usize % display_height;
+
+        // SUPER-CHIP draws a 16x16 sprite, two bytes per row, for DXY0
+        let (sprite_width, rows) = if height == 0 && self.platform.superchip() {
+            (16, 16)
+        } else {
+            (8, height as usize)
+        };
+        let bytes_per_row = sprite_width / 8;
+        let sprite_len = rows * bytes_per_row;
 
-        println!("Drawing sprite at I ({:#04x}) of height {} at coords ({}, {})", self.i, height, x_coord, y_coord);
+        // XO-CHIP reads one sprite per selected plane, back to back starting at I
+        let planes: Vec<u8> = [1, 2].into_iter().filter(|plane| self.display.planes() & plane != 0).collect();
+
+        trace!("Drawing sprite at I ({:#04x}) of height {} at coords ({}, {})", self.i, rows, x_coord, y_coord);
+
+        self.check_index(sprite_len * planes.len())?;
 
         self.registers[0xF] = 0;
 
-        for row in 0..height as usize {
-            let sprite = self.memory[self.i + row];
+        for (index, &plane) in planes.iter().enumerate() {
+            let start = self.i + index * sprite_len;
 
-            for col in 0..8 as usize {
-                let x = x_coord + col;
-                let y = y_coord + row;
+            for row in 0..rows {
+                let offset = start + row * bytes_per_row;
+                let sprite = self.memory[offset..offset + bytes_per_row]
+                    .iter()
+                    .fold(0u16, |bits, &byte| (bits << 8) | byte as u16);
 
-                if (sprite & (1 << (7 - col))) > 0 {
-                    self.display.flip_pixel(x, y);
-                    if !self.display.get_pixel(x, y) {
+                for col in 0..sprite_width {
+                    let Some((x, y)) = self.sprite_pixel(x_coord + col, y_coord + row, width, display_height) else {
+                        continue;
+                    };
+
+                    if (sprite & (1 << (sprite_width - 1 - col))) > 0 && self.display.flip_pixel(x, y, plane) {
                         self.registers[0xF] = 1;
                     }
                 }
             }
         }
+
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+        }
+        Ok(())
     }
 
+    /// MEGA-CHIP sprites are `sprite_width` x `sprite_height` palette indices, 0 being transparent.
+    fn draw_indexed(&mut self, x: usize, y: usize) -> Result<(), FaultKind> {
+        let width = self.display.width();
+        let height = self.display.height();
+
+        let x_coord = self.registers[x] as usize % width;
+        let y_coord = self.registers[y] as usize % height;
+
+        trace!("Drawing {}x{} colour sprite at I ({:#08x}) at coords ({}, {})",
+            self.sprite_width, self.sprite_height, self.i, x_coord, y_coord);
+
+        self.check_index(self.sprite_width * self.sprite_height)?;
+
+        self.registers[0xF] = 0;
+
+        for row in 0..self.sprite_height {
+            for col in 0..self.sprite_width {
+                let Some((x, y)) = self.sprite_pixel(x_coord + col, y_coord + row, width, height) else {
+                    continue;
+                };
+
+                let index = self.memory[self.i + row * self.sprite_width + col];
+                if index == 0 {
+                    continue;
+                }
+
+                // colour 0 is the empty background, so it never counts as a collision
+                let replaced = self.display.draw_indexed(x, y, index, self.blend_mode);
+                if replaced != 0 && replaced == self.collision_colour {
+                    self.registers[0xF] = 1;
+                }
+            }
+        }
+
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+        }
+        Ok(())
+    }
+
+    /// Where a sprite pixel lands once it runs off the screen: nowhere under the
+    /// clip quirk, otherwise wrapped around to the opposite edge.
+    fn sprite_pixel(&self, x: usize, y: usize, width: usize, height: usize) -> Option<(usize, usize)> {
+        if x < width && y < height {
+            return Some((x, y));
+        }
+        if self.quirks.clip {
+            return None;
+        }
+        Some((x % width, y % height))
+    }
+
+    /// FX0A: re-executes itself until a key is entered, then stores it in VX.
     fn wait_for_key(&mut self, address: usize) {
-        println!("Waiting for key...");
+        if self.key_wait == KeyWait::Idle {
+            trace!("Waiting for key...");
+            self.key_wait = KeyWait::Waiting;
+        }
+
+        let entered = match (self.key_wait, self.key_wait_mode) {
+            (KeyWait::Held(key), _) => (!self.keypad.is_pressed(key)).then_some(key),
+            (_, mode) => match (0..=0xF).find(|&key| self.keypad.is_pressed(key)) {
+                Some(key) if mode == KeyWaitMode::Press => Some(key),
+                Some(key) => {
+                    self.key_wait = KeyWait::Held(key);
+                    None
+                }
+                None => None,
+            },
+        };
 
-        for i in 0..0xF {
-            if self.keypad.is_pressed(i) {
-                self.registers[address] = i as u8;
-                return
+        match entered {
+            Some(key) => {
+                trace!("Key {:X} entered", key);
+                self.key_wait = KeyWait::Idle;
+                self.registers[address] = key as u8;
             }
+            None => self.pc -= 2,
         }
-        
-        self.pc -= 2;
     }
 
     fn register_add(&mut self, address: usize, a: u32, b: u32) {
-        println!("adding {} to {} and setting it to register V{:01x}", a, b, address);
+        trace!("adding {} to {} and setting it to register V{:01x}", a, b, address);
 
         let sum = a + b;
 
@@ -290,7 +1016,7 @@ impl Cpu {
     }
 
     fn register_sub(&mut self, address: usize, a: u8, b: u8) {
-        println!("subtract {} from {} and setting it to register V{:01x}", a, b, address);
+        trace!("subtract {} from {} and setting it to register V{:01x}", a, b, address);
 
         self.registers[0xF] = 0;
         if a > b {
@@ -300,30 +1026,49 @@ impl Cpu {
         self.register_set(address, a.wrapping_sub(b));
     }
 
-    fn load_memory_from_registers(&mut self, end: usize) {
-        println!("Loading registers to memory starting at {}", self.i);
+    fn load_memory_from_registers(&mut self, end: usize) -> Result<(), FaultKind> {
+        trace!("Loading registers to memory starting at {}", self.i);
+        self.check_index(end + 1)?;
 
         for reg_index in 0..=end {
             self.memory[self.i + reg_index] = self.registers[reg_index];
         }
+        self.memory_write = Some(self.i..self.i + end + 1);
+        Ok(())
     }
     
-    fn load_registers_from_memory(&mut self, end: usize) {
-        println!("Loading memory starting at {} to addresses", self.i);
+    fn load_registers_from_memory(&mut self, end: usize) -> Result<(), FaultKind> {
+        trace!("Loading memory starting at {} to addresses", self.i);
+        self.check_index(end + 1)?;
 
         for reg_index in 0..=end {
             self.registers[reg_index] = self.memory[self.i + reg_index];
         }
+        Ok(())
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), FaultKind> {
+        trace!("Loading audio pattern from memory starting at {}", self.i);
+        self.check_index(PATTERN_SIZE)?;
+
+        let mut bits = [0; PATTERN_SIZE];
+        bits.copy_from_slice(&self.memory[self.i..self.i + PATTERN_SIZE]);
+        self.audio_pattern = Some(bits);
+        Ok(())
     }
 
-    fn store_decimal_digits(&mut self, value: u8) {
+    fn store_decimal_digits(&mut self, value: u8) -> Result<(), FaultKind> {
+        self.check_index(3)?;
+
         self.memory[self.i] = value / 100;
         self.memory[self.i + 1] = (value % 100) / 10;
         self.memory[self.i + 2] = value % 10;
+        self.memory_write = Some(self.i..self.i + 3);
+        Ok(())
     }
 
     fn set_random(&mut self, address: usize, value: u8) {
-        println!("Setting register V{:01x} to random", address);
+        trace!("Setting register V{:01x} to random", address);
 
         let mut rng = rand::thread_rng();
         let r = rng.gen_range(0..=0xFF);
@@ -331,83 +1076,208 @@ impl Cpu {
         self.registers[address] = value & r;
     }
 
+    /// Skips the next instruction, which is four bytes long if it is XO-CHIP's F000 NNNN or MEGA-CHIP's 01NN NNNN.
+    fn skip(&mut self) {
+        let long_index = self.pc + 1 < self.memory.len()
+            && match self.platform {
+                Platform::XoChip => self.memory[self.pc] == 0xF0 && self.memory[self.pc + 1] == 0x00,
+                Platform::MegaChip => self.memory[self.pc] == 0x01,
+                _ => false,
+            };
+
+        self.pc += if long_index { 4 } else { 2 };
+    }
+
     fn skip_if_equal(&mut self, a: u8, b: u8) {
         if a == b {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_if_not_equal(&mut self, a: u8, b: u8) {
         if a != b {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_if_pressed(&mut self, key: usize) {
-        if self.keypad.is_pressed(key) {
-            self.pc += 2;
+        if self.keypad.is_pressed(key & 0xF) {
+            self.skip();
         }
     }
 
     fn skip_if_not_pressed(&mut self, key: usize) {
-        if !self.keypad.is_pressed(key) {
-            self.pc += 2;
+        if !self.keypad.is_pressed(key & 0xF) {
+            self.skip();
         }
     }
 
-    fn shift_left(&mut self, address: usize) {
-        println!("Left shift of {} in (register V{:01x})", self.registers[address], address);
-        self.registers[0xF] = self.registers[address] & 0x80;
-        self.registers[address] <<= 1;
+    /// 5XY1 adds the two octal digits packed in each nibble separately, dropping the carries.
+    fn add_nibbles(&mut self, address: usize, a: u8, b: u8) {
+        let high = ((a & 0x70) + (b & 0x70)) & 0x70;
+        let low = ((a & 0x07) + (b & 0x07)) & 0x07;
+        self.register_set(address, high | low);
     }
 
-    fn shift_right(&mut self, address: usize) {
-        println!("Right shift of {} (register V{:01x})", self.registers[address], address);
-        self.registers[0xF] = self.registers[address] & 0x1;
-        self.registers[address] >>= 1;
+    /// BXY0 takes its area from VX and VX+1 in zones: the low nibble is the first
+    /// column (8 pixels) or row (4 pixels), the high nibble how many more follow.
+    /// BXYN instead colours N pixel rows of the column at VX, starting at row VX+1.
+    fn set_zone_colour(&mut self, x: usize, y: usize, n: u8) {
+        let horizontal = self.registers[x] as usize;
+        let vertical = self.registers[(x + 1) & 0xF] as usize;
+        let colour = self.registers[y] & 0x7;
+
+        trace!("Colouring zone at ({:#04x}, {:#04x}) with colour {}", horizontal, vertical, colour);
+
+        if n == 0 {
+            let x = (horizontal & 0xF) * 8;
+            let width = ((horizontal >> 4) + 1) * 8;
+            let y = (vertical & 0xF) * 4;
+            let height = ((vertical >> 4) + 1) * 4;
+            self.display.set_zone_colour(x, y, width, height, colour);
+        } else {
+            self.display.set_zone_colour(horizontal, vertical, 8, n as usize, colour);
+        }
     }
 
-    fn push_subroutine(&mut self, address: usize) {
-        println!("Pushing address {} to stack", address);
-        self.stack.push(self.pc);
-        self.pc = address
+    fn long_index_high(&mut self, high: u8) -> Result<(), FaultKind> {
+        let low = self.fetch()?;
+        let address = ((high as usize) << 16) | low as usize;
+        trace!("setting I to long address {:#08x}", address);
+        self.index_set(address);
+        Ok(())
     }
 
-    fn pop_subroutine(&mut self) {
-        match self.stack.pop() {
-            Some(address ) => {
-                println!("Popped address {} from stack", address);
-                self.pc = address;
-            }
-            None => {}
+    /// 02NN loads NN 0xAARRGGBB colours from I into palette entries 1 to NN.
+    fn load_palette(&mut self, count: usize) -> Result<(), FaultKind> {
+        trace!("Loading {} palette colours from memory starting at {}", count, self.i);
+        self.check_index(count * 4)?;
+
+        let colours: Vec<u32> = self.memory[self.i..self.i + count * 4]
+            .chunks(4)
+            .map(|argb| u32::from_be_bytes([argb[0], argb[1], argb[2], argb[3]]))
+            .collect();
+        self.display.set_palette(1, &colours);
+        Ok(())
+    }
+
+    fn play_sample(&mut self, looping: bool) -> Result<(), FaultKind> {
+        self.check_index(SAMPLE_HEADER_SIZE)?;
+
+        let header = &self.memory[self.i..self.i + SAMPLE_HEADER_SIZE];
+        let rate = u16::from_be_bytes([header[0], header[1]]);
+        let len = ((header[2] as usize) << 16) | ((header[3] as usize) << 8) | header[4] as usize;
+
+        trace!("Playing {} byte sample at {} Hz from memory starting at {}", len, rate, self.i);
+        self.check_index(SAMPLE_HEADER_SIZE + len)?;
+
+        let start = self.i + SAMPLE_HEADER_SIZE;
+        let data = self.memory[start..start + len].to_vec();
+        self.sample_command = Some(SampleCommand::Play(Sample { rate, data, looping }));
+        Ok(())
+    }
+
+    fn long_index(&mut self) -> Result<(), FaultKind> {
+        let address = self.fetch()?;
+        trace!("setting I to long address {:#06x}", address);
+        self.index_set(address as usize);
+        Ok(())
+    }
+
+    /// 5XY2 and 5XY3 work on VX through VY, in descending order if X > Y, and leave I unchanged.
+    fn register_range(x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
+        let count = x.abs_diff(y) + 1;
+        (0..count).map(move |offset| (offset, if x <= y { x + offset } else { x - offset }))
+    }
+
+    fn save_register_range(&mut self, x: usize, y: usize) -> Result<(), FaultKind> {
+        trace!("Saving V{:01x}-V{:01x} to memory starting at {}", x, y, self.i);
+        self.check_index(x.abs_diff(y) + 1)?;
+
+        for (offset, reg_index) in Self::register_range(x, y) {
+            self.memory[self.i + offset] = self.registers[reg_index];
+        }
+        self.memory_write = Some(self.i..self.i + x.abs_diff(y) + 1);
+        Ok(())
+    }
+
+    fn load_register_range(&mut self, x: usize, y: usize) -> Result<(), FaultKind> {
+        trace!("Loading memory starting at {} to V{:01x}-V{:01x}", self.i, x, y);
+        self.check_index(x.abs_diff(y) + 1)?;
+
+        for (offset, reg_index) in Self::register_range(x, y) {
+            self.registers[reg_index] = self.memory[self.i + offset];
+        }
+        Ok(())
+    }
+
+    fn shift_left(&mut self, address: usize, value: u8) {
+        trace!("Left shift of {} in (register V{:01x})", value, address);
+        self.registers[address] = value << 1;
+        self.registers[0xF] = value >> 7;
+    }
+
+    fn shift_right(&mut self, address: usize, value: u8) {
+        trace!("Right shift of {} (register V{:01x})", value, address);
+        self.registers[address] = value >> 1;
+        self.registers[0xF] = value & 0x1;
+    }
+
+    fn push_subroutine(&mut self, address: usize) -> Result<(), FaultKind> {
+        if self.stack.len() >= self.stack_depth {
+            return Err(FaultKind::StackOverflow { depth: self.stack_depth });
         }
+
+        trace!("Pushing address {} to stack", address);
+        self.stack.push(self.pc);
+        self.pc = address;
+        Ok(())
+    }
+
+    fn pop_subroutine(&mut self) -> Result<(), FaultKind> {
+        let address = self.stack.pop().ok_or(FaultKind::StackUnderflow)?;
+
+        trace!("Popped address {} from stack", address);
+        self.pc = address;
+        Ok(())
     }
 
     fn jump(&mut self, address: usize) {
-        println!("Jumping to address {} in memory", address);
+        trace!("Jumping to address {} in memory", address);
         self.pc = address;
     }
 
     fn register_set(&mut self, address: usize, value: u8) {
-        println!("Setting register V{:01x} to {}", address, value);
+        trace!("Setting register V{:01x} to {}", address, value);
         self.registers[address] = value;
     }
 
+    fn logical_set(&mut self, address: usize, value: u8) {
+        self.register_set(address, value);
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
     fn index_set(&mut self, address: usize) {
-        println!("Setting index register to address {}", address);
+        trace!("Setting index register to address {}", address);
         self.i = address;
+        self.index_write = true;
     }
 
-    fn set_delay_timer(&mut self, value: u8) {
-        println!("Setting delay timer to {:#04x}", value);
+    pub fn set_delay_timer(&mut self, value: u8) {
+        trace!("Setting delay timer to {:#04x}", value);
         self.delay_timer = value;
     }
 
-    fn set_sound_timer(&mut self, value: u8) {
-        println!("Setting sound timer to {:#04x}", value);
-        if value > 0 {
-            self.sound.start_sound();
-        }
+    pub fn set_sound_timer(&mut self, value: u8) {
+        trace!("Setting sound timer to {:#04x}", value);
         self.sound_timer = value;
     }
-}  
\ No newline at end of file
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new(Quirks::default())
+    }
+}
@@ -1,61 +1,378 @@
-use sdl2::video::Window;
-use sdl2::render::Canvas;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use crate::platform::Platform;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
 
-use crate::emulator::{WIDTH, HEIGHT};
+// classic resolution, the two-page VIP hires interpreter, and the SUPER-CHIP high resolution mode
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const TWO_PAGE_HEIGHT: usize = 64;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
 
-pub struct Display {
-    scale: i32,
-    pub pixels: [[bool; WIDTH]; HEIGHT],
-    canvas: Canvas<Window>,
+// XO-CHIP draws to two bitplanes, each pixel holds one bit per plane
+pub const PLANES: u8 = 2;
+
+// CHIP-8X colour zones are one byte wide; zones start out red and the background blue
+pub const ZONE_WIDTH: usize = 8;
+pub const ZONE_COLOURS: u8 = 8;
+pub const BACKGROUND_COLOURS: u8 = 4;
+const DEFAULT_ZONE_COLOUR: u8 = 1;
+
+// MEGA-CHIP colour 0 is transparent, the other 255 come from 02NN
+pub const PALETTE_SIZE: usize = 256;
+
+/// How a MEGA-CHIP sprite pixel is combined with the colour already on screen (080N).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
 }
 
+impl BlendMode {
+    pub fn from_mode(n: u8) -> Option<BlendMode> {
+        match n {
+            0 => Some(BlendMode::Normal),
+            1 => Some(BlendMode::Alpha25),
+            2 => Some(BlendMode::Alpha50),
+            3 => Some(BlendMode::Alpha75),
+            4 => Some(BlendMode::Add),
+            5 => Some(BlendMode::Multiply),
+            _ => None,
+        }
+    }
+
+    /// The N of the 080N that selects this mode.
+    pub fn mode(self) -> u8 {
+        match self {
+            BlendMode::Normal => 0,
+            BlendMode::Alpha25 => 1,
+            BlendMode::Alpha50 => 2,
+            BlendMode::Alpha75 => 3,
+            BlendMode::Add => 4,
+            BlendMode::Multiply => 5,
+        }
+    }
+
+    // combines two 0xAARRGGBB colours channel by channel, the result is opaque
+    fn blend(self, src: u32, dst: u32) -> u32 {
+        let channel = |colour: u32, shift: u32| (colour >> shift) & 0xFF;
+        let mix = |s: u32, d: u32| match self {
+            BlendMode::Normal => s,
+            BlendMode::Alpha25 => (s + 3 * d) / 4,
+            BlendMode::Alpha50 => (s + d) / 2,
+            BlendMode::Alpha75 => (3 * s + d) / 4,
+            BlendMode::Add => (s + d).min(0xFF),
+            BlendMode::Multiply => s * d / 0xFF,
+        };
+
+        [16, 8, 0].iter().fold(0xFF00_0000, |colour, &shift| {
+            colour | mix(channel(src, shift), channel(dst, shift)) << shift
+        })
+    }
+}
+
+/// A framebuffer of up to two bitplanes whose resolution can change at runtime.
+///
+/// Each pixel is a colour index between 0 and 3: bit 0 is plane 1 and bit 1
+/// is plane 2. Drawing, clearing and scrolling only touch the selected planes.
+///
+/// In MEGA-CHIP mode the display is 256x192, each pixel holds a palette index
+/// instead, and `colours()` holds what is actually shown after blending.
+///
+/// CHIP-8X adds a colour attribute layer on top: every 8x1 pixel zone has one
+/// of eight foreground colours, drawn over one of four background colours.
+pub struct Display {
+    width: usize,
+    height: usize,
+    // row-major, `width * height` long
+    pixels: Vec<u8>,
+    // bitmask of the planes FN01 selected
+    planes: u8,
+    megachip: bool,
+    // 0xAARRGGBB, loaded by 02NN
+    palette: [u32; PALETTE_SIZE],
+    // the blended MEGA-CHIP picture, empty outside MEGA-CHIP mode
+    colours: Vec<u32>,
+    // set by 05NN, 0 is fully transparent and 255 opaque
+    alpha: u8,
+    // CHIP-8X foreground colour per zone, row-major, empty without colour zones
+    attributes: Vec<u8>,
+    // CHIP-8X background colour, stepped by 02A0
+    background: u8,
+}
 
 impl Display {
-    pub fn new(window: Window, scale: i32) -> Result<Self, String> {
-        let mut canvas: Canvas<Window> = window.into_canvas().build().map_err(|e| e.to_string())?;
-        
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.present();
+    pub fn new() -> Self {
+        let pixels: Vec<u8> = vec![0; WIDTH * HEIGHT];
+
+        Display {
+            width: WIDTH,
+            height: HEIGHT,
+            pixels,
+            planes: 1,
+            megachip: false,
+            palette: [0; PALETTE_SIZE],
+            colours: Vec::new(),
+            alpha: 0xFF,
+            attributes: Vec::new(),
+            background: 0,
+        }
+    }
 
-        let pixels: [[bool; WIDTH]; HEIGHT] = [[false; WIDTH]; HEIGHT];
+    pub fn width(&self) -> usize {
+        self.width
+    }
 
-        Ok(Display { scale, pixels, canvas })
+    pub fn height(&self) -> usize {
+        self.height
     }
 
-    pub fn update_display(&mut self) {
-        let pixel_size = self.scale as u32;
+    pub fn hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
 
-        for i in 0..HEIGHT {
-            for j in 0..WIDTH {
-                let x = j as i32 * self.scale;
-                let y = i as i32 * self.scale;
-                if self.pixels[i][j] {
-                    self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-                }
-                else {
-                    self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-                }
-                let _ = self.canvas.fill_rect(Rect::new(x, y, pixel_size, pixel_size));
+    /// Switches between 64x32 and 128x64, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires { (HIRES_WIDTH, HIRES_HEIGHT) } else { (WIDTH, HEIGHT) };
+        self.resize(width, height);
+    }
+
+    pub fn megachip(&self) -> bool {
+        self.megachip
+    }
+
+    /// Switches MEGA-CHIP's 256x192 colour mode on (0011) or off (0010), clearing the screen.
+    pub fn set_megachip(&mut self, megachip: bool) {
+        self.megachip = megachip;
+        if megachip {
+            self.resize(MEGA_WIDTH, MEGA_HEIGHT);
+        } else {
+            self.resize(WIDTH, HEIGHT);
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+        self.colours = if self.megachip { vec![0; width * height] } else { Vec::new() };
+        if self.colour_zones() {
+            self.attributes = vec![DEFAULT_ZONE_COLOUR; width.div_ceil(ZONE_WIDTH) * height];
+        }
+    }
+
+    pub fn colour_zones(&self) -> bool {
+        !self.attributes.is_empty()
+    }
+
+    /// Adds or removes the CHIP-8X colour attribute layer, resetting every zone's colour.
+    pub fn set_colour_zones(&mut self, enabled: bool) {
+        self.attributes = if enabled {
+            vec![DEFAULT_ZONE_COLOUR; self.width.div_ceil(ZONE_WIDTH) * self.height]
+        } else {
+            Vec::new()
+        };
+        self.background = 0;
+    }
+
+    /// The foreground colour (0-7) of the zone containing pixel (x, y).
+    pub fn zone_colour(&self, x: usize, y: usize) -> u8 {
+        let zones_per_row = self.width.div_ceil(ZONE_WIDTH);
+        self.attributes[(y % self.height) * zones_per_row + (x % self.width) / ZONE_WIDTH]
+    }
+
+    /// Colours every zone overlapping the `width` x `height` pixel area at (x, y), wrapping at the edges.
+    pub fn set_zone_colour(&mut self, x: usize, y: usize, width: usize, height: usize, colour: u8) {
+        let zones_per_row = self.width.div_ceil(ZONE_WIDTH);
+        let first_zone = (x % self.width) / ZONE_WIDTH;
+        let zones = width.div_ceil(ZONE_WIDTH).min(zones_per_row);
+
+        for row in 0..height.min(self.height) {
+            let y = (y + row) % self.height;
+            for zone in 0..zones {
+                let zone = (first_zone + zone) % zones_per_row;
+                self.attributes[y * zones_per_row + zone] = colour % ZONE_COLOURS;
             }
         }
     }
 
+    /// The CHIP-8X background colour (0-3).
+    pub fn background_colour(&self) -> u8 {
+        self.background
+    }
+
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUND_COLOURS;
+    }
+
+    /// The framebuffer as rows of `width()` colour indices.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// The MEGA-CHIP picture as rows of `width()` 0xAARRGGBB colours.
+    pub fn colours(&self) -> &[u32] {
+        &self.colours
+    }
+
+    pub fn palette(&self) -> &[u32; PALETTE_SIZE] {
+        &self.palette
+    }
+
+    /// Overwrites the palette from index `start`, skipping colours that don't fit.
+    pub fn set_palette(&mut self, start: usize, colours: &[u32]) {
+        for (entry, &colour) in self.palette.iter_mut().skip(start).zip(colours) {
+            *entry = colour;
+        }
+    }
+
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1);
+    }
+
     pub fn clear(&mut self) {
-        self.pixels = [[false; WIDTH]; HEIGHT];
+        let planes = self.plane_mask();
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= !planes);
+        self.colours.iter_mut().for_each(|colour| *colour = 0);
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize, plane: u8) -> bool {
+        self.pixels[self.index(x, y)] & plane != 0
+    }
+
+    /// Flips the pixel in `plane`, returning true if that turned it off.
+    pub fn flip_pixel(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let index = self.index(x, y);
+        self.pixels[index] ^= plane;
+        self.pixels[index] & plane == 0
+    }
+
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.usize(self.width);
+        writer.usize(self.height);
+        writer.bytes(&self.pixels);
+        writer.u8(self.planes);
+        writer.bool(self.megachip);
+        self.palette.iter().for_each(|&colour| writer.u32(colour));
+        writer.usize(self.colours.len());
+        self.colours.iter().for_each(|&colour| writer.u32(colour));
+        writer.u8(self.alpha);
+        writer.bytes(&self.attributes);
+        writer.u8(self.background);
+    }
+
+    pub(crate) fn read_state(reader: &mut StateReader, platform: Platform) -> Result<Display, SaveStateError> {
+        let width = reader.usize()?;
+        let height = reader.usize()?;
+        if !platform.has_resolution(width, height) {
+            return Err(SaveStateError::Corrupt);
+        }
+        let pixels = reader.exact_bytes(width * height)?.to_vec();
+        let planes = reader.u8()?;
+        let megachip = reader.bool()?;
+
+        let mut palette = [0; PALETTE_SIZE];
+        for colour in palette.iter_mut() {
+            *colour = reader.u32()?;
+        }
+
+        let colours_len = reader.usize()?;
+        if colours_len != if megachip { width * height } else { 0 } {
+            return Err(SaveStateError::Corrupt);
+        }
+        let colours = (0..colours_len).map(|_| reader.u32()).collect::<Result<Vec<u32>, _>>()?;
+
+        let alpha = reader.u8()?;
+        let attributes = reader.bytes()?.to_vec();
+        if !attributes.is_empty() && attributes.len() != width.div_ceil(ZONE_WIDTH) * height {
+            return Err(SaveStateError::Corrupt);
+        }
+        let background = reader.u8()?;
+
+        Ok(Display { width, height, pixels, planes, megachip, palette, colours, alpha, attributes, background })
+    }
+
+    /// Draws palette colour `index` in MEGA-CHIP mode, returning the index it replaced.
+    pub fn draw_indexed(&mut self, x: usize, y: usize, index: u8, blend: BlendMode) -> u8 {
+        let i = self.index(x, y);
+        let replaced = self.pixels[i];
+
+        self.pixels[i] = index;
+        self.colours[i] = blend.blend(self.palette[index as usize], self.colours[i]);
+        replaced
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    // moves the selected planes by (dx, dy), filling the uncovered edge with 0
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let planes = self.plane_mask();
+        let old = self.pixels.clone();
+        let old_colours = self.colours.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let src = if (0..self.width as isize).contains(&src_x) && (0..self.height as isize).contains(&src_y) {
+                    Some(src_y as usize * self.width + src_x as usize)
+                } else {
+                    None
+                };
+
+                let index = y * self.width + x;
+                self.pixels[index] = (old[index] & !planes) | src.map_or(0, |src| old[src] & planes);
+                if self.megachip {
+                    self.colours[index] = src.map_or(0, |src| old_colours[src]);
+                }
+            }
+        }
     }
 
-    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
-        self.pixels[y % HEIGHT][x % WIDTH]
+    // MEGA-CHIP pixels are whole palette indices rather than plane bits
+    fn plane_mask(&self) -> u8 {
+        if self.megachip { 0xFF } else { self.planes }
     }
 
-    pub fn flip_pixel(&mut self, x: usize, y: usize) {
-        self.pixels[y % HEIGHT][x % WIDTH] ^= true;
+    // coordinates past the edge wrap; whether a sprite may get there at all is the CPU's clip quirk
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y % self.height) * self.width + x % self.width
     }
+}
 
-    pub fn render_canvas(&mut self) {
-        self.canvas.present();
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
     }
-}
\ No newline at end of file
+}
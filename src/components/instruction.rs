@@ -0,0 +1,359 @@
+use std::fmt;
+
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+/// A decoded CHIP-8 instruction. `x` and `y` are register numbers, `nn` an
+/// 8-bit immediate and `nnn` a 12-bit address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump { nnn: usize },
+    /// 2NNN
+    Call { nnn: usize },
+    /// 3XNN
+    SkipEqImm { x: usize, nn: u8 },
+    /// 4XNN
+    SkipNeImm { x: usize, nn: u8 },
+    /// 5XY0
+    SkipEq { x: usize, y: usize },
+    /// 6XNN
+    LoadImm { x: usize, nn: u8 },
+    /// 7XNN
+    AddImm { x: usize, nn: u8 },
+    /// 8XY0
+    Move { x: usize, y: usize },
+    /// 8XY1
+    Or { x: usize, y: usize },
+    /// 8XY2
+    And { x: usize, y: usize },
+    /// 8XY3
+    Xor { x: usize, y: usize },
+    /// 8XY4
+    Add { x: usize, y: usize },
+    /// 8XY5
+    Sub { x: usize, y: usize },
+    /// 8XY6
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7
+    SubReverse { x: usize, y: usize },
+    /// 8XYE
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0
+    SkipNe { x: usize, y: usize },
+    /// ANNN
+    LoadIndex { nnn: usize },
+    /// BNNN, or BXNN under the jump quirk
+    JumpOffset { x: usize, nnn: usize },
+    /// CXNN
+    Random { x: usize, nn: u8 },
+    /// DXYN, or a 16x16 sprite for DXY0 on SUPER-CHIP
+    Draw { x: usize, y: usize, n: u8 },
+    /// EX9E
+    SkipKey { x: usize },
+    /// EXA1
+    SkipNotKey { x: usize },
+    /// FX07
+    LoadDelay { x: usize },
+    /// FX0A
+    WaitKey { x: usize },
+    /// FX15
+    SetDelay { x: usize },
+    /// FX18
+    SetSound { x: usize },
+    /// FX1E
+    AddIndex { x: usize },
+    /// FX29
+    LoadFont { x: usize },
+    /// FX33
+    StoreBcd { x: usize },
+    /// FX55
+    Store { x: usize },
+    /// FX65
+    Load { x: usize },
+
+    // SUPER-CHIP 1.1
+
+    /// 00CN
+    ScrollDown { n: u8 },
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// FX30
+    LoadBigFont { x: usize },
+    /// FX75
+    StoreFlags { x: usize },
+    /// FX85
+    LoadFlags { x: usize },
+
+    // XO-CHIP
+
+    /// 00DN
+    ScrollUp { n: u8 },
+    /// 5XY2
+    SaveRange { x: usize, y: usize },
+    /// 5XY3
+    LoadRange { x: usize, y: usize },
+    /// F000 NNNN, the 16-bit address is in the word after the opcode
+    LongIndex,
+    /// FN01
+    Plane { n: u8 },
+    /// F002
+    Audio,
+    /// FX3A
+    Pitch { x: usize },
+
+    // MEGA-CHIP
+
+    /// 0010
+    MegaOff,
+    /// 0011
+    MegaOn,
+    /// 01NN NNNN, the low 16 bits of the 24-bit address are in the word after the opcode
+    LongIndexHigh { nn: u8 },
+    /// 02NN
+    LoadPalette { nn: u8 },
+    /// 03NN
+    SpriteWidth { nn: u8 },
+    /// 04NN
+    SpriteHeight { nn: u8 },
+    /// 05NN
+    ScreenAlpha { nn: u8 },
+    /// 060N
+    PlaySample { n: u8 },
+    /// 0700
+    StopSample,
+    /// 080N
+    SetBlend { n: u8 },
+    /// 09NN
+    CollisionColour { nn: u8 },
+
+    // CHIP-8X
+
+    /// 02A0
+    CycleBackground,
+    /// 5XY1
+    AddNibbles { x: usize, y: usize },
+    /// BXY0 colours a block of 8x4 zones, BXYN a single column of N rows
+    ZoneColour { x: usize, y: usize, n: u8 },
+    /// EXF2
+    SkipKey2 { x: usize },
+    /// EXF5
+    SkipNotKey2 { x: usize },
+    /// FXF8
+    OutputPort { x: usize },
+    /// FXFB
+    InputPort { x: usize },
+}
+
+impl Instruction {
+    /// Decodes an opcode, or returns None if it isn't part of `platform`'s instruction set.
+    pub fn decode(instr: u16, platform: Platform) -> Option<Instruction> {
+        let superchip = platform.superchip();
+        let xochip = platform.xochip();
+        let megachip = platform.megachip();
+        let two_page = platform == Platform::Chip8Hires;
+        let chip8x = platform.chip8x();
+
+        let x: usize = ((instr & 0x0F00) >> 8) as usize;
+        let y: usize = ((instr & 0x00F0) >> 4) as usize;
+
+        let n: u8 = (instr & 0x000F) as u8;
+        let nn: u8 = (instr & 0x00FF) as u8;
+        let nnn: usize = (instr & 0x0FFF) as usize; // used as 12-bit memory address
+
+        let instruction = match (instr & 0xF000) >> 12 {
+            0x0 => {
+                match nnn {
+                    0x0E0 => Instruction::Clear,
+                    // the hires interpreter's own clear for the 64x64 screen
+                    0x230 if two_page => Instruction::Clear,
+                    0x2A0 if chip8x => Instruction::CycleBackground,
+                    0x0EE => Instruction::Return,
+                    0x0C0..=0x0CF if superchip => Instruction::ScrollDown { n },
+                    0x0D0..=0x0DF if xochip => Instruction::ScrollUp { n },
+                    0x0B0..=0x0BF if megachip => Instruction::ScrollUp { n },
+                    0x010 if megachip => Instruction::MegaOff,
+                    0x011 if megachip => Instruction::MegaOn,
+                    0x100..=0x1FF if megachip => Instruction::LongIndexHigh { nn },
+                    0x200..=0x2FF if megachip => Instruction::LoadPalette { nn },
+                    0x300..=0x3FF if megachip => Instruction::SpriteWidth { nn },
+                    0x400..=0x4FF if megachip => Instruction::SpriteHeight { nn },
+                    0x500..=0x5FF if megachip => Instruction::ScreenAlpha { nn },
+                    0x600..=0x601 if megachip => Instruction::PlaySample { n },
+                    0x700 if megachip => Instruction::StopSample,
+                    0x800..=0x805 if megachip => Instruction::SetBlend { n },
+                    0x900..=0x9FF if megachip => Instruction::CollisionColour { nn },
+                    0x0FB if superchip => Instruction::ScrollRight,
+                    0x0FC if superchip => Instruction::ScrollLeft,
+                    0x0FD if superchip => Instruction::Exit,
+                    0x0FE if superchip => Instruction::Lores,
+                    0x0FF if superchip => Instruction::Hires,
+                    _ => return None,
+                }
+            },
+            0x1 => Instruction::Jump { nnn },
+            0x2 => Instruction::Call { nnn },
+            0x3 => Instruction::SkipEqImm { x, nn },
+            0x4 => Instruction::SkipNeImm { x, nn },
+            0x5 if n == 0 => Instruction::SkipEq { x, y },
+            0x5 if n == 1 && chip8x => Instruction::AddNibbles { x, y },
+            0x5 if n == 2 && xochip => Instruction::SaveRange { x, y },
+            0x5 if n == 3 && xochip => Instruction::LoadRange { x, y },
+            0x6 => Instruction::LoadImm { x, nn },
+            0x7 => Instruction::AddImm { x, nn },
+            0x8 => {
+                match n {
+                    0x0 => Instruction::Move { x, y },
+                    0x1 => Instruction::Or { x, y },
+                    0x2 => Instruction::And { x, y },
+                    0x3 => Instruction::Xor { x, y },
+                    0x4 => Instruction::Add { x, y },
+                    0x5 => Instruction::Sub { x, y },
+                    0x6 => Instruction::ShiftRight { x, y },
+                    0x7 => Instruction::SubReverse { x, y },
+                    0xE => Instruction::ShiftLeft { x, y },
+                    _ => return None,
+                }
+            }
+            0x9 if n == 0 => Instruction::SkipNe { x, y },
+            0xa => Instruction::LoadIndex { nnn },
+            // CHIP-8X gives up BNNN for its colour instruction
+            0xb if chip8x => Instruction::ZoneColour { x, y, n },
+            0xb => Instruction::JumpOffset { x, nnn },
+            0xc => Instruction::Random { x, nn },
+            0xd => Instruction::Draw { x, y, n },
+            0xe => {
+                match nn {
+                    0x9E => Instruction::SkipKey { x },
+                    0xA1 => Instruction::SkipNotKey { x },
+                    0xF2 if chip8x => Instruction::SkipKey2 { x },
+                    0xF5 if chip8x => Instruction::SkipNotKey2 { x },
+                    _ => return None,
+                }
+            }
+            0xf => {
+                match nn {
+                    0x00 if x == 0 && xochip => Instruction::LongIndex,
+                    0x01 if xochip => Instruction::Plane { n: x as u8 },
+                    0x02 if x == 0 && xochip => Instruction::Audio,
+                    0x07 => Instruction::LoadDelay { x },
+                    0x0A => Instruction::WaitKey { x },
+                    0x15 => Instruction::SetDelay { x },
+                    0x18 => Instruction::SetSound { x },
+                    0x1E => Instruction::AddIndex { x },
+                    0x29 => Instruction::LoadFont { x },
+                    0x30 if superchip => Instruction::LoadBigFont { x },
+                    0x33 => Instruction::StoreBcd { x },
+                    0x3A if xochip => Instruction::Pitch { x },
+                    0x55 => Instruction::Store { x },
+                    0x65 => Instruction::Load { x },
+                    0x75 if superchip => Instruction::StoreFlags { x },
+                    0x85 if superchip => Instruction::LoadFlags { x },
+                    0xF8 if chip8x => Instruction::OutputPort { x },
+                    0xFB if chip8x => Instruction::InputPort { x },
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// The mnemonic as the CPU runs it under `quirks`: the same as `Display`,
+    /// except that BXNN names the register the jump quirk really adds.
+    pub fn mnemonic(&self, quirks: Quirks) -> String {
+        match *self {
+            Instruction::JumpOffset { x, nnn } if quirks.jump => format!("JP V{:X}, {:#05x}", x, nnn),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Classic mnemonics, as in Cowgod's CHIP-8 technical reference.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump { nnn } => write!(f, "JP {:#05x}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL {:#05x}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04x}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04x}", x, nn),
+            Instruction::SkipEq { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LoadImm { x, nn } => write!(f, "LD V{:X}, {:#04x}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04x}", x, nn),
+            Instruction::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNe { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex { nnn } => write!(f, "LD I, {:#05x}", nnn),
+            Instruction::JumpOffset { nnn, .. } => write!(f, "JP V0, {:#05x}", nnn),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, {:#04x}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}-V{:X}", x, y),
+            Instruction::LongIndex => write!(f, "LD I, LONG"),
+            Instruction::Plane { n } => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::MegaOff => write!(f, "MEGAOFF"),
+            Instruction::MegaOn => write!(f, "MEGAON"),
+            Instruction::LongIndexHigh { nn } => write!(f, "LDHI I, #{:02X}....", nn),
+            Instruction::LoadPalette { nn } => write!(f, "LDPAL {}", nn),
+            Instruction::SpriteWidth { nn } => write!(f, "SPRW {}", nn),
+            Instruction::SpriteHeight { nn } => write!(f, "SPRH {}", nn),
+            Instruction::ScreenAlpha { nn } => write!(f, "ALPHA {}", nn),
+            Instruction::PlaySample { n } => write!(f, "DIGISND {}", n),
+            Instruction::StopSample => write!(f, "STOPSND"),
+            Instruction::SetBlend { n } => write!(f, "BMODE {}", n),
+            Instruction::CollisionColour { nn } => write!(f, "CCOL {}", nn),
+            Instruction::CycleBackground => write!(f, "BGCOL"),
+            Instruction::AddNibbles { x, y } => write!(f, "ADD V{:X}, V{:X}, NIBBLES", x, y),
+            Instruction::ZoneColour { x, y, n } => write!(f, "COL V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey2 { x } => write!(f, "SKP2 V{:X}", x),
+            Instruction::SkipNotKey2 { x } => write!(f, "SKNP2 V{:X}", x),
+            Instruction::OutputPort { x } => write!(f, "OUT V{:X}", x),
+            Instruction::InputPort { x } => write!(f, "IN V{:X}", x),
+        }
+    }
+}
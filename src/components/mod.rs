@@ -1,9 +1,14 @@
+mod audio;
 mod cpu;
 mod display;
+mod instruction;
 mod keypad;
-mod sound;
 
-pub use cpu::Cpu;
-pub use display::Display;
+pub use audio::{AudioPattern, Sample, SampleCommand, DEFAULT_PITCH, PATTERN_SIZE, SAMPLE_HEADER_SIZE};
+pub use cpu::{Cpu, KeyWaitMode, UnknownOpcodePolicy, BIG_FONTS, FONTS, TRACE_TARGET};
+pub use display::{
+    BlendMode, Display, WIDTH, HEIGHT, HIRES_WIDTH, HIRES_HEIGHT, MEGA_WIDTH, MEGA_HEIGHT, TWO_PAGE_HEIGHT,
+    BACKGROUND_COLOURS, ZONE_COLOURS, ZONE_WIDTH,
+};
+pub use instruction::Instruction;
 pub use keypad::Keypad;
-pub use sound::Sound;
\ No newline at end of file
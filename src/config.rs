@@ -1,39 +1,26 @@
-use sdl2::keyboard::Keycode;
-
-pub const ROM_PATH: &str = "src/roms";
+use crate::components::{KeyWaitMode, UnknownOpcodePolicy};
+use crate::quirks::QuirkProfile;
+use crate::timing::TimingMode;
 
 pub const CPU_HZ: u64 = 400;
 pub const DISPLAY_HZ: u64 = 60;
+pub const TIMING: TimingMode = TimingMode::Fixed;
 
-// set to true for newer models starting with
-// CHIP-48 and SUPER-CHIP
-// affects 8XY6, 8XYE, BXXX instructions
-pub const USE_NEW: bool = false;
+// used when neither the command line nor the ROM's extension picks a profile
+pub const DEFAULT_QUIRKS: QuirkProfile = QuirkProfile::Modern;
 
-// affects FX55 and FX65 instructions
-pub const USE_NEW_LOAD: bool = true;
+// deepest nesting of subroutine calls before the CPU faults
+pub const STACK_DEPTH: usize = 16;
 
-pub const FONT_LOAD_START: usize = 0x050;
-pub const ROM_LOAD_START: usize = 0x200;
+// skip and report them, as the emulator always has; stray data words shouldn't stop a ROM
+pub const UNKNOWN_OPCODES: UnknownOpcodePolicy = UnknownOpcodePolicy::Warn;
 
-pub const PAUSE_KEY: Keycode = Keycode::Space;
+// FX0A completes on key release, as on the COSMAC VIP
+pub const KEY_WAIT: KeyWaitMode = KeyWaitMode::Release;
 
-// assumes QWERTY keyboard is used
-pub const KEYMAPPINGS: [Keycode; 16] = [
-    Keycode::Num1,
-    Keycode::Num2,
-    Keycode::Num3,
-    Keycode::Num4,
-    Keycode::Q,
-    Keycode::W,
-    Keycode::E,
-    Keycode::R,
-    Keycode::A,
-    Keycode::S,
-    Keycode::D,
-    Keycode::F,
-    Keycode::Z,
-    Keycode::X,
-    Keycode::C,
-    Keycode::V
-];
\ No newline at end of file
+// ten seconds of history at 60 frames per second, in at most 8 MiB
+pub const REWIND_DEPTH: usize = 600;
+pub const REWIND_BUDGET: usize = 8 * 1024 * 1024;
+
+pub const FONT_LOAD_START: usize = 0x050;
+pub const ROM_LOAD_START: usize = 0x200;
@@ -0,0 +1,604 @@
+//! A line-based debugger console.
+//!
+//! While the emulator is in [`EmulatorState::Debugging`](crate::EmulatorState)
+//! it stops running instructions and takes [`DebugCommand`]s from a
+//! [`Debugger`] instead. Lines come from a [`StdinLines`] reader thread so the
+//! window keeps showing the current frame while the prompt waits.
+//!
+//! [`Breakpoints`] send a running program back to the prompt when it reaches
+//! an address or opcode, when a condition becomes true, or when it writes to
+//! watched memory or I.
+
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SendError, Sender, TryRecvError};
+use std::thread;
+
+use crate::Chip8;
+use crate::components::Instruction;
+
+const PROMPT: &str = "(chip-chop) ";
+
+pub const HELP: &str = "\
+Commands:
+  step [N]           run N instructions (default: 1); the timers only tick
+                     when a sprite waits for the display interrupt
+  continue           leave the debugger and run normally
+  regs               print V0-VF, I and PC
+  stack              print the return addresses, innermost last
+  mem ADDR [LEN]     dump LEN bytes of memory from ADDR (default: 16)
+  set REG VALUE      change V0-VF, I, PC, DT or ST, e.g. set V3 0x10
+  timers             print the delay and sound timers
+  break ADDR         stop before the instruction at ADDR runs
+  break op PATTERN   stop before any opcode matching PATTERN, where X, Y,
+                     N and K match any digit, e.g. DXYN or FX0A
+  break if REG OP VALUE
+                     stop once a condition becomes true, e.g. V5 == 3;
+                     OP is one of == != < <= > >=
+  watch mem ADDR [LEN]
+                     stop after anything writes to LEN bytes from ADDR
+                     (default: 1)
+  watch I            stop after anything sets I
+  breakpoints        list the breakpoints and watchpoints
+  delete ID          remove a breakpoint or watchpoint
+  help               print this help";
+
+/// Something the debugger can change with `set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugRegister {
+    V(usize),
+    I,
+    Pc,
+    Delay,
+    Sound,
+}
+
+impl fmt::Display for DebugRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugRegister::V(x) => write!(f, "V{:X}", x),
+            DebugRegister::I => f.write_str("I"),
+            DebugRegister::Pc => f.write_str("PC"),
+            DebugRegister::Delay => f.write_str("DT"),
+            DebugRegister::Sound => f.write_str("ST"),
+        }
+    }
+}
+
+impl FromStr for DebugRegister {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("unknown register '{}' (expected V0-VF, I, PC, DT or ST)", s);
+
+        match &*s.to_uppercase() {
+            "I" => Ok(DebugRegister::I),
+            "PC" => Ok(DebugRegister::Pc),
+            "DT" => Ok(DebugRegister::Delay),
+            "ST" => Ok(DebugRegister::Sound),
+            register => {
+                let digit = register.strip_prefix('V').ok_or_else(invalid)?;
+                match usize::from_str_radix(digit, 16) {
+                    Ok(x) if digit.len() == 1 => Ok(DebugRegister::V(x)),
+                    _ => Err(invalid()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    Step(usize),
+    Continue,
+    Registers,
+    Stack,
+    Memory { address: usize, len: usize },
+    Set { register: DebugRegister, value: usize },
+    Timers,
+    Break(Breakpoint),
+    Breakpoints,
+    Delete(usize),
+    Help,
+}
+
+impl FromStr for DebugCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        let expect_args = |min: usize, max: usize, usage: &str| {
+            if args.len() < min || args.len() > max {
+                return Err(format!("usage: {}", usage));
+            }
+            Ok(())
+        };
+
+        match command {
+            "step" | "s" => {
+                expect_args(0, 1, "step [N]")?;
+                let count = args.first().map_or(Ok(1), |count| parse_number(count))?;
+                Ok(DebugCommand::Step(count))
+            }
+            "continue" | "c" => {
+                expect_args(0, 0, "continue")?;
+                Ok(DebugCommand::Continue)
+            }
+            "regs" | "r" => {
+                expect_args(0, 0, "regs")?;
+                Ok(DebugCommand::Registers)
+            }
+            "stack" => {
+                expect_args(0, 0, "stack")?;
+                Ok(DebugCommand::Stack)
+            }
+            "mem" | "m" => {
+                expect_args(1, 2, "mem ADDR [LEN]")?;
+                let address = parse_number(args[0])?;
+                let len = args.get(1).map_or(Ok(16), |len| parse_number(len))?;
+                Ok(DebugCommand::Memory { address, len })
+            }
+            "set" => {
+                expect_args(2, 2, "set REG VALUE")?;
+                Ok(DebugCommand::Set { register: args[0].parse()?, value: parse_number(args[1])? })
+            }
+            "timers" | "t" => {
+                expect_args(0, 0, "timers")?;
+                Ok(DebugCommand::Timers)
+            }
+            "break" | "b" => Ok(DebugCommand::Break(parse_break(&args)?)),
+            "watch" | "w" => Ok(DebugCommand::Break(parse_watch(&args)?)),
+            "breakpoints" | "info" => {
+                expect_args(0, 0, "breakpoints")?;
+                Ok(DebugCommand::Breakpoints)
+            }
+            "delete" | "d" => {
+                expect_args(1, 1, "delete ID")?;
+                Ok(DebugCommand::Delete(parse_number(args[0])?))
+            }
+            "help" | "h" | "?" => Ok(DebugCommand::Help),
+            _ => Err(format!("unknown command '{}', type help for a list", command)),
+        }
+    }
+}
+
+fn parse_break(args: &[&str]) -> Result<Breakpoint, String> {
+    match args {
+        ["op", pattern] => Breakpoint::opcode(pattern),
+        ["if", register, comparison, value] => Ok(Breakpoint::Condition {
+            register: register.parse()?,
+            comparison: comparison.parse()?,
+            value: parse_number(value)?,
+        }),
+        [address] => Ok(Breakpoint::Address(parse_number(address)?)),
+        _ => Err(String::from("usage: break ADDR | break op PATTERN | break if REG OP VALUE")),
+    }
+}
+
+fn parse_watch(args: &[&str]) -> Result<Breakpoint, String> {
+    match args {
+        ["I" | "i"] => Ok(Breakpoint::Index),
+        ["mem", address, rest @ ..] if rest.len() <= 1 => {
+            let start = parse_number(address)?;
+            let len = rest.first().map_or(Ok(1), |len| parse_number(len))?;
+            if len == 0 {
+                return Err(String::from("a watched range needs at least one byte"));
+            }
+            if start.checked_add(len).is_none() {
+                return Err(format!("{:#x}+{:#x} runs past the end of the address space", start, len));
+            }
+            Ok(Breakpoint::Memory { start, len })
+        }
+        _ => Err(String::from("usage: watch mem ADDR [LEN] | watch I")),
+    }
+}
+
+// hex with a 0x prefix, otherwise decimal
+fn parse_number(value: &str) -> Result<usize, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", value))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds(self, a: usize, b: usize) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        })
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "==" => Ok(Comparison::Eq),
+            "!=" => Ok(Comparison::Ne),
+            "<" => Ok(Comparison::Lt),
+            "<=" => Ok(Comparison::Le),
+            ">" => Ok(Comparison::Gt),
+            ">=" => Ok(Comparison::Ge),
+            _ => Err(format!("unknown comparison '{}' (expected ==, !=, <, <=, > or >=)", s)),
+        }
+    }
+}
+
+/// A rule that stops the program and opens the debugger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at this address runs.
+    Address(usize),
+    /// Before any opcode with `value` in the nibbles `mask` selects; `pattern` is how it was typed.
+    Opcode { pattern: [char; 4], value: u16, mask: u16 },
+    /// After an instruction makes the comparison true.
+    Condition { register: DebugRegister, comparison: Comparison, value: usize },
+    /// After an instruction writes to any of these bytes.
+    Memory { start: usize, len: usize },
+    /// After an instruction sets I.
+    Index,
+}
+
+impl Breakpoint {
+    /// Parses a pattern like `DXYN`, where hex digits must match and X, Y, N and K match anything.
+    pub fn opcode(pattern: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid opcode pattern '{}', expected four hex digits or X, Y, N, K like DXYN", pattern);
+
+        let chars: Vec<char> = pattern.to_uppercase().chars().collect();
+        let pattern: [char; 4] = chars.try_into().map_err(|_| invalid())?;
+
+        let (mut value, mut mask) = (0, 0);
+        for c in pattern {
+            value <<= 4;
+            mask <<= 4;
+            match c {
+                'X' | 'Y' | 'N' | 'K' => (),
+                _ => {
+                    value |= c.to_digit(16).ok_or_else(invalid)? as u16;
+                    mask |= 0xF;
+                }
+            }
+        }
+        Ok(Breakpoint::Opcode { pattern, value, mask })
+    }
+
+    fn is_watchpoint(&self) -> bool {
+        matches!(self, Breakpoint::Memory { .. } | Breakpoint::Index)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "break {:#05x}", address),
+            Breakpoint::Opcode { pattern, .. } => write!(f, "break op {}", pattern.iter().collect::<String>()),
+            Breakpoint::Condition { register, comparison, value } => {
+                write!(f, "break if {} {} {:#x}", register, comparison, value)
+            }
+            Breakpoint::Memory { start, len } => write!(f, "watch mem {:#05x} {}", start, len),
+            Breakpoint::Index => f.write_str("watch I"),
+        }
+    }
+}
+
+/// The breakpoints and watchpoints set so far, numbered from 1 in the order they were added.
+#[derive(Default)]
+pub struct Breakpoints {
+    rules: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    // whether each condition held before the current instruction, in the order of `rules`
+    held: Vec<bool>,
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        self.next_id += 1;
+        self.rules.push((self.next_id, breakpoint));
+        self.next_id
+    }
+
+    /// Removes the rule numbered `id`, returning false if there is none.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.rules.len();
+        self.rules.retain(|&(rule_id, _)| rule_id != id);
+        self.rules.len() != count
+    }
+
+    pub fn list(&self) -> String {
+        if self.rules.is_empty() {
+            return String::from("no breakpoints or watchpoints");
+        }
+        self.rules.iter().map(|(id, rule)| format!("{:2}: {}", id, rule)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Checks the rules that fire before the instruction at PC runs, returning the first one that does.
+    /// Pass `resuming` for the instruction the prompt was left at, so it doesn't stop there again.
+    pub fn before(&mut self, cpu: &Chip8, resuming: bool) -> Option<(usize, Breakpoint)> {
+        if self.rules.is_empty() {
+            return None;
+        }
+
+        self.held.clear();
+        self.held.extend(self.rules.iter().map(|&(_, rule)| match rule {
+            Breakpoint::Condition { register, comparison, value } => comparison.holds(read(cpu, register), value),
+            _ => false,
+        }));
+
+        if resuming {
+            return None;
+        }
+        let opcode = opcode_at(cpu, cpu.pc());
+        self.rules.iter().copied().find(|&(_, rule)| match rule {
+            Breakpoint::Address(address) => address == cpu.pc(),
+            Breakpoint::Opcode { value, mask, .. } => opcode.is_some_and(|opcode| opcode & mask == value),
+            _ => false,
+        })
+    }
+
+    /// Checks the rules that fire once an instruction has run: watched writes and conditions that just became true.
+    pub fn after(&self, cpu: &Chip8) -> Option<(usize, Breakpoint)> {
+        if self.rules.is_empty() {
+            return None;
+        }
+
+        let written = cpu.last_memory_write();
+        self.rules.iter().zip(&self.held).map(|(&rule, &held)| (rule, held)).find_map(|((id, rule), held)| {
+            let fired = match rule {
+                Breakpoint::Condition { register, comparison, value } => {
+                    !held && comparison.holds(read(cpu, register), value)
+                }
+                Breakpoint::Memory { start, len } => {
+                    written.as_ref().is_some_and(|written| written.start < start.saturating_add(len) && start < written.end)
+                }
+                Breakpoint::Index => cpu.wrote_index(),
+                _ => false,
+            };
+            fired.then_some((id, rule))
+        })
+    }
+}
+
+/// How a hit is reported, e.g. `breakpoint 2 (break op DXYN)`.
+pub fn describe_hit(id: usize, breakpoint: Breakpoint) -> String {
+    let kind = if breakpoint.is_watchpoint() { "watchpoint" } else { "breakpoint" };
+    format!("{} {} ({})", kind, id, breakpoint)
+}
+
+fn read(cpu: &Chip8, register: DebugRegister) -> usize {
+    match register {
+        DebugRegister::V(x) => cpu.registers()[x] as usize,
+        DebugRegister::I => cpu.i(),
+        DebugRegister::Pc => cpu.pc(),
+        DebugRegister::Delay => cpu.delay_timer() as usize,
+        DebugRegister::Sound => cpu.sound_timer() as usize,
+    }
+}
+
+fn opcode_at(cpu: &Chip8, address: usize) -> Option<u16> {
+    let memory = cpu.memory();
+    (address + 1 < memory.len()).then(|| u16::from_be_bytes([memory[address], memory[address + 1]]))
+}
+
+/// One thread reading stdin for everyone who asks for a line. A prompt that goes away while
+/// waiting, like the debugger's when the window is closed, can't leave a blocked read behind
+/// to swallow what is typed next: the line goes to whoever asks after it instead.
+#[derive(Clone)]
+pub struct StdinLines {
+    requests: Sender<Sender<Option<String>>>,
+}
+
+impl StdinLines {
+    /// Starts the reader thread, which only touches stdin while someone is waiting for a line.
+    pub fn spawn() -> Self {
+        let (requests, requested) = mpsc::channel::<Sender<Option<String>>>();
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            // a line whose asker had gone by the time it was typed
+            let mut unclaimed = None;
+            for reply in requested {
+                let line = unclaimed.take().unwrap_or_else(|| {
+                    let mut line = String::new();
+                    match stdin.lock().read_line(&mut line) {
+                        Ok(len) if len > 0 => Some(line),
+                        _ => None,
+                    }
+                });
+                if let Err(SendError(line)) = reply.send(line) {
+                    unclaimed = Some(line);
+                }
+            }
+        });
+
+        StdinLines { requests }
+    }
+
+    /// Asks for the next line, which arrives on the returned channel, or None once stdin is closed.
+    pub fn request(&self) -> Receiver<Option<String>> {
+        let (reply, line) = mpsc::channel();
+        // if the reader is gone the channel just disconnects, which callers treat like a closed stdin
+        let _ = self.requests.send(reply);
+        line
+    }
+
+    /// Waits for the next line, or returns None once stdin is closed.
+    pub fn read_line(&self) -> Option<String> {
+        self.request().recv().ok().flatten()
+    }
+}
+
+/// Reads commands from stdin and prints the answers to stdout.
+pub struct Debugger {
+    stdin: StdinLines,
+    // a prompt has been printed and this is where its line will arrive
+    pending: Option<Receiver<Option<String>>>,
+    pub breakpoints: Breakpoints,
+    // where the prompt handed control back, so a breakpoint there doesn't fire again straight away
+    resume_at: Option<usize>,
+}
+
+impl Debugger {
+    /// Takes its commands from `stdin`, which may be shared with other prompts.
+    pub fn new(stdin: StdinLines) -> Self {
+        Debugger { stdin, pending: None, breakpoints: Breakpoints::default(), resume_at: None }
+    }
+
+    /// Returns the next line typed at the prompt, printing the prompt first if needed.
+    pub fn poll(&mut self) -> Option<String> {
+        let pending = self.pending.get_or_insert_with(|| {
+            print!("{}", PROMPT);
+            let _ = io::stdout().flush();
+            self.stdin.request()
+        });
+
+        let line = match pending.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.pending = None;
+        // a closed stdin lets the program run on rather than stall at the prompt
+        Some(line.unwrap_or_else(|| String::from("continue")))
+    }
+
+    pub fn print(&self, text: &str) {
+        println!("{}", text);
+    }
+
+    /// Called when leaving the prompt, so the instruction it stopped at can run.
+    pub fn resume(&mut self, cpu: &Chip8) {
+        self.resume_at = Some(cpu.pc());
+    }
+
+    /// The breakpoint to stop at before the instruction at PC runs, if any.
+    pub fn check_before(&mut self, cpu: &Chip8) -> Option<(usize, Breakpoint)> {
+        let resuming = self.resume_at == Some(cpu.pc());
+        self.breakpoints.before(cpu, resuming)
+    }
+
+    /// The breakpoint or watchpoint the instruction that just ran set off, if any.
+    pub fn check_after(&mut self, cpu: &Chip8) -> Option<(usize, Breakpoint)> {
+        // FX0A and the display wait keep PC where it is until the instruction really finishes
+        if !cpu.waiting_for_key() && !cpu.waiting_for_vblank() {
+            self.resume_at = None;
+        }
+        self.breakpoints.after(cpu)
+    }
+}
+
+/// The instruction at PC, e.g. `0x0202  6005  LD V0, 0x05`.
+pub fn location(cpu: &Chip8) -> String {
+    instruction_at(cpu, cpu.pc())
+}
+
+/// The instruction at `address`, in the same form as [`location`].
+pub fn instruction_at(cpu: &Chip8, address: usize) -> String {
+    let Some(opcode) = opcode_at(cpu, address) else {
+        return format!("{:#06x}  outside memory", address);
+    };
+
+    let mnemonic = match Instruction::decode(opcode, cpu.platform()) {
+        Some(instruction) => instruction.mnemonic(cpu.quirks()),
+        None => String::from("???"),
+    };
+    format!("{:#06x}  {:04x}  {}", address, opcode, mnemonic)
+}
+
+pub fn registers(cpu: &Chip8) -> String {
+    let mut text = String::new();
+    for (x, value) in cpu.registers().iter().enumerate() {
+        let separator = if x % 8 == 7 { "\n" } else { "  " };
+        let _ = write!(text, "V{:X}={:02x}{}", x, value, separator);
+    }
+    let _ = write!(text, "I={:#05x}  PC={:#05x}", cpu.i(), cpu.pc());
+    text
+}
+
+pub fn stack(cpu: &Chip8) -> String {
+    if cpu.stack().is_empty() {
+        return String::from("stack is empty");
+    }
+    cpu.stack()
+        .iter()
+        .enumerate()
+        .map(|(depth, address)| format!("{:2}: {:#05x}", depth, address))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Sixteen bytes per line, each line starting with its address.
+pub fn memory(cpu: &Chip8, address: usize, len: usize) -> Result<String, String> {
+    let memory = cpu.memory();
+    let end = address.checked_add(len).filter(|&end| end <= memory.len()).ok_or_else(|| {
+        format!("{:#x}+{:#x} is outside the {:#x} bytes of memory", address, len, memory.len())
+    })?;
+
+    let lines: Vec<String> = memory[address..end]
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let bytes: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("{:#06x}  {}", address + i * 16, bytes.join(" "))
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+pub fn timers(cpu: &Chip8) -> String {
+    format!("DT={:#04x} ({})  ST={:#04x} ({})", cpu.delay_timer(), cpu.delay_timer(), cpu.sound_timer(), cpu.sound_timer())
+}
+
+/// Applies `set`, refusing values the register can't hold.
+pub fn set(cpu: &mut Chip8, register: DebugRegister, value: usize) -> Result<(), String> {
+    let memory_len = cpu.memory().len();
+    let byte = || u8::try_from(value).map_err(|_| format!("{:#x} doesn't fit in a byte", value));
+    let address = || {
+        if value >= memory_len {
+            return Err(format!("{:#x} is outside the {:#x} bytes of memory", value, memory_len));
+        }
+        Ok(value)
+    };
+
+    match register {
+        DebugRegister::V(x) => cpu.set_register(x, byte()?),
+        DebugRegister::I => cpu.set_i(address()?),
+        DebugRegister::Pc => cpu.set_pc(address()?),
+        DebugRegister::Delay => cpu.set_delay_timer(byte()?),
+        DebugRegister::Sound => cpu.set_sound_timer(byte()?),
+    }
+    Ok(())
+}
@@ -0,0 +1,350 @@
+//! Static disassembly of ROMs.
+//!
+//! Starting from the entry point, [`Disassembly`] follows every jump, call and
+//! skip it can resolve to work out which bytes are instructions; anything it
+//! never reaches is printed as data. Jump and call targets get labels, and so
+//! do the addresses `I` is pointed at.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+
+use crate::components::Instruction;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+// data bytes per line
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Source that Octo can assemble again.
+    Octo,
+    /// A listing of addresses, opcodes and Cowgod's mnemonics.
+    Classic,
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Syntax::Octo => "octo",
+            Syntax::Classic => "classic",
+        })
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "octo" => Ok(Syntax::Octo),
+            "classic" => Ok(Syntax::Classic),
+            _ => Err(format!("unknown syntax '{}' (expected octo or classic)", s)),
+        }
+    }
+}
+
+// ordered so that a stronger reason to name an address wins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Data,
+    Jump,
+    Subroutine,
+    Entry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Data,
+    // the first byte of an instruction this many bytes long
+    Code(Instruction, usize),
+    // the rest of an instruction
+    Operand,
+}
+
+/// A ROM split into code and data.
+pub struct Disassembly {
+    platform: Platform,
+    // only change how instructions read, never which bytes are code
+    quirks: Quirks,
+    start: usize,
+    rom: Vec<u8>,
+    bytes: Vec<Byte>,
+    labels: BTreeMap<usize, Label>,
+}
+
+impl Disassembly {
+    /// Traces `rom` as `platform` would load and run it.
+    pub fn new(rom: &[u8], platform: Platform) -> Self {
+        let start = platform.rom_start();
+        let mut disassembly = Disassembly {
+            platform,
+            quirks: Quirks::default(),
+            start,
+            rom: rom.to_vec(),
+            bytes: vec![Byte::Data; rom.len()],
+            labels: BTreeMap::new(),
+        };
+
+        disassembly.labels.insert(start, Label::Entry);
+        disassembly.trace(start);
+        disassembly
+    }
+
+    /// Renders instructions as they run under `quirks`; the default is `Quirks::default()`.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    fn trace(&mut self, entry: usize) {
+        let mut pending = vec![entry];
+
+        while let Some(mut address) = pending.pop() {
+            // decode straight on until something ends the run or it meets code already seen
+            while let Some((instruction, len)) = self.decode(address) {
+                let offset = address - self.start;
+                if self.bytes[offset..offset + len].iter().any(|&byte| byte != Byte::Data) {
+                    break;
+                }
+                self.bytes[offset] = Byte::Code(instruction, len);
+                self.bytes[offset + 1..offset + len].fill(Byte::Operand);
+
+                let next = address + len;
+                match instruction {
+                    Instruction::Jump { nnn } => {
+                        self.add_label(nnn, Label::Jump);
+                        pending.push(nnn);
+                        break;
+                    }
+                    // V0 (or VX) is only known at run time, but the table usually starts at NNN
+                    Instruction::JumpOffset { nnn, .. } => {
+                        self.add_label(nnn, Label::Jump);
+                        pending.push(nnn);
+                        break;
+                    }
+                    Instruction::Call { nnn } => {
+                        self.add_label(nnn, Label::Subroutine);
+                        pending.push(nnn);
+                    }
+                    Instruction::Return | Instruction::Exit => break,
+                    Instruction::SkipEqImm { .. }
+                    | Instruction::SkipNeImm { .. }
+                    | Instruction::SkipEq { .. }
+                    | Instruction::SkipNe { .. }
+                    | Instruction::SkipKey { .. }
+                    | Instruction::SkipNotKey { .. }
+                    | Instruction::SkipKey2 { .. }
+                    | Instruction::SkipNotKey2 { .. } => {
+                        // skipping a long instruction takes all four of its bytes
+                        let skipped = self.decode(next).map_or(2, |(_, len)| len);
+                        pending.push(next + skipped);
+                    }
+                    Instruction::LoadIndex { nnn } => self.add_label(nnn, Label::Data),
+                    Instruction::LongIndex => self.add_label(self.word(address + 2), Label::Data),
+                    Instruction::LongIndexHigh { nn } => {
+                        self.add_label(((nn as usize) << 16) | self.word(address + 2), Label::Data)
+                    }
+                    _ => (),
+                }
+                address = next;
+            }
+        }
+    }
+
+    /// The instruction at `address` and its length, if it's inside the ROM and a valid opcode.
+    fn decode(&self, address: usize) -> Option<(Instruction, usize)> {
+        let offset = address.checked_sub(self.start)?;
+        if offset + 2 > self.rom.len() {
+            return None;
+        }
+
+        let instruction = Instruction::decode(self.word(address) as u16, self.platform)?;
+        let len = match instruction {
+            Instruction::LongIndex | Instruction::LongIndexHigh { .. } => 4,
+            _ => 2,
+        };
+        (offset + len <= self.rom.len()).then_some((instruction, len))
+    }
+
+    // big-endian, zero past the end of the ROM
+    fn word(&self, address: usize) -> usize {
+        let byte = |address: usize| {
+            address.checked_sub(self.start).and_then(|offset| self.rom.get(offset)).copied().unwrap_or(0) as usize
+        };
+        (byte(address) << 8) | byte(address + 1)
+    }
+
+    fn add_label(&mut self, address: usize, label: Label) {
+        if address < self.start || address >= self.start + self.rom.len() {
+            return;
+        }
+        let existing = self.labels.entry(address).or_insert(label);
+        *existing = (*existing).max(label);
+    }
+
+    /// The label for `address`, if it has one that can be printed where it points.
+    fn label(&self, address: usize) -> Option<String> {
+        let label = self.labels.get(&address)?;
+        if self.bytes[address - self.start] == Byte::Operand {
+            return None;
+        }
+
+        Some(match label {
+            Label::Entry => String::from("main"),
+            Label::Subroutine => format!("sub_{:03x}", address),
+            Label::Jump => format!("label_{:03x}", address),
+            Label::Data => format!("data_{:03x}", address),
+        })
+    }
+
+    // a label if there is one, else the bare address
+    fn target(&self, address: usize) -> String {
+        self.label(address).unwrap_or_else(|| format!("{:#05x}", address))
+    }
+
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut text = String::new();
+        let mut offset = 0;
+
+        while offset < self.rom.len() {
+            let address = self.start + offset;
+            if let Some(label) = self.label(address) {
+                match syntax {
+                    Syntax::Octo => {
+                        let _ = writeln!(text, ": {}", label);
+                    }
+                    Syntax::Classic => {
+                        let _ = writeln!(text, "{}:", label);
+                    }
+                }
+            }
+
+            match self.bytes[offset] {
+                Byte::Code(instruction, len) => {
+                    let line = match syntax {
+                        Syntax::Octo => self.octo(address, instruction, len),
+                        Syntax::Classic => self.classic(address, instruction, len),
+                    };
+                    let _ = writeln!(text, "{}", line);
+                    offset += len;
+                }
+                Byte::Data | Byte::Operand => {
+                    let len = self.data_run(offset);
+                    let _ = writeln!(text, "{}", self.data(address, &self.rom[offset..offset + len], syntax));
+                    offset += len;
+                }
+            }
+        }
+        text
+    }
+
+    // data up to the end of the line, the next label or the next instruction
+    fn data_run(&self, offset: usize) -> usize {
+        let mut len = 1;
+        while len < DATA_PER_LINE && offset + len < self.rom.len() {
+            let next = offset + len;
+            if matches!(self.bytes[next], Byte::Code(..)) || self.label(self.start + next).is_some() {
+                break;
+            }
+            len += 1;
+        }
+        len
+    }
+
+    fn data(&self, address: usize, bytes: &[u8], syntax: Syntax) -> String {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        match syntax {
+            Syntax::Octo => format!("\t{}", bytes.join(" ")),
+            Syntax::Classic => format!("{:#06x}  {:8}  DB {}", address, "", bytes.join(", ")),
+        }
+    }
+
+    fn opcode_bytes(&self, address: usize, len: usize) -> String {
+        let offset = address - self.start;
+        self.rom[offset..offset + len].iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn classic(&self, address: usize, instruction: Instruction, len: usize) -> String {
+        let mnemonic = match instruction {
+            Instruction::Jump { nnn } => format!("JP {}", self.target(nnn)),
+            Instruction::Call { nnn } => format!("CALL {}", self.target(nnn)),
+            Instruction::LoadIndex { nnn } => format!("LD I, {}", self.target(nnn)),
+            // under the jump quirk BXNN adds VX, X being the top nibble of the target
+            Instruction::JumpOffset { x, nnn } if self.quirks.jump => format!("JP V{:X}, {}", x, self.target(nnn)),
+            Instruction::JumpOffset { nnn, .. } => format!("JP V0, {}", self.target(nnn)),
+            Instruction::LongIndex => format!("LD I, LONG {}", self.target(self.word(address + 2))),
+            _ => instruction.mnemonic(self.quirks),
+        };
+        format!("{:#06x}  {:8}  {}", address, self.opcode_bytes(address, len), mnemonic)
+    }
+
+    fn octo(&self, address: usize, instruction: Instruction, len: usize) -> String {
+        let statement = match instruction {
+            Instruction::Clear => String::from("clear"),
+            Instruction::Return => String::from("return"),
+            Instruction::Jump { nnn } => format!("jump {}", self.target(nnn)),
+            // Octo calls a subroutine by naming it
+            Instruction::Call { nnn } => match self.label(nnn) {
+                Some(label) => label,
+                None => format!(":call {:#05x}", nnn),
+            },
+            // Octo's conditions say when the next instruction runs, so the skips read inverted
+            Instruction::SkipEqImm { x, nn } => format!("if v{:x} != 0x{:02X} then", x, nn),
+            Instruction::SkipNeImm { x, nn } => format!("if v{:x} == 0x{:02X} then", x, nn),
+            Instruction::SkipEq { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::SkipNe { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::SkipKey { x } => format!("if v{:x} -key then", x),
+            Instruction::SkipNotKey { x } => format!("if v{:x} key then", x),
+            Instruction::LoadImm { x, nn } => format!("v{:x} := 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => format!("v{:x} += 0x{:02X}", x, nn),
+            Instruction::Move { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SubReverse { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::LoadIndex { nnn } => format!("i := {}", self.target(nnn)),
+            Instruction::JumpOffset { nnn, .. } => format!("jump0 {}", self.target(nnn)),
+            Instruction::Random { x, nn } => format!("v{:x} := random 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::LoadDelay { x } => format!("v{:x} := delay", x),
+            Instruction::WaitKey { x } => format!("v{:x} := key", x),
+            Instruction::SetDelay { x } => format!("delay := v{:x}", x),
+            Instruction::SetSound { x } => format!("buzzer := v{:x}", x),
+            Instruction::AddIndex { x } => format!("i += v{:x}", x),
+            Instruction::LoadFont { x } => format!("i := hex v{:x}", x),
+            Instruction::StoreBcd { x } => format!("bcd v{:x}", x),
+            Instruction::Store { x } => format!("save v{:x}", x),
+            Instruction::Load { x } => format!("load v{:x}", x),
+            Instruction::ScrollDown { n } => format!("scroll-down {}", n),
+            Instruction::ScrollRight => String::from("scroll-right"),
+            Instruction::ScrollLeft => String::from("scroll-left"),
+            Instruction::Exit => String::from("exit"),
+            Instruction::Lores => String::from("lores"),
+            Instruction::Hires => String::from("hires"),
+            Instruction::LoadBigFont { x } => format!("i := bighex v{:x}", x),
+            Instruction::StoreFlags { x } => format!("saveflags v{:x}", x),
+            Instruction::LoadFlags { x } => format!("loadflags v{:x}", x),
+            Instruction::ScrollUp { n } => format!("scroll-up {}", n),
+            Instruction::SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LongIndex => format!("i := long {}", self.target(self.word(address + 2))),
+            Instruction::Plane { n } => format!("plane {}", n),
+            Instruction::Audio => String::from("audio"),
+            Instruction::Pitch { x } => format!("pitch := v{:x}", x),
+            // Octo has no syntax for the hires clear, MEGA-CHIP or CHIP-8X, so keep the bytes
+            _ => {
+                let offset = address - self.start;
+                let bytes: Vec<String> =
+                    self.rom[offset..offset + len].iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                format!("{} # {}", bytes.join(" "), instruction)
+            }
+        };
+        format!("\t{}", statement)
+    }
+}
@@ -1,100 +1,508 @@
-use sdl2::video::Window;
-use sdl2::{Sdl, VideoSubsystem};
-
 use std::time::{Duration, Instant};
 use std::thread::sleep;
 
-use crate::components::{Cpu, Display, Keypad, Sound};
-use crate::config::{CPU_HZ, DISPLAY_HZ};
+use log::{info, warn};
+
+use crate::Chip8;
+use crate::components::{AudioPattern, SampleCommand};
+use crate::backend::{AudioSink, Hotkey, InputSource, VideoSink};
+use crate::config::{CPU_HZ, DISPLAY_HZ, TIMING};
+use crate::debugger::{self, Breakpoint, DebugCommand, DebugRegister, Debugger};
+use crate::rewind::RewindBuffer;
+use crate::savestate::SaveSlots;
+use crate::timing::{TimingMode, VIP_CYCLES_PER_FRAME, VIP_INTERRUPT_CYCLES};
 
-pub const WIDTH: usize = 64;
-pub const HEIGHT: usize = 32;
+// what came of running one instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Ran,
+    // a breakpoint or watchpoint sent the emulator to the debugger
+    Break,
+    // 00FD ended the program
+    Exited,
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EmulatorState {
     Playing,
     Paused,
+    /// Stopped at the debugger prompt, running only what it's told to.
+    Debugging,
     Stopped,
 }
 
-pub struct Emulator {
-    cpu: Cpu,
+pub struct Emulator<V: VideoSink, A: AudioSink, I: InputSource> {
+    cpu: Chip8,
+    video: V,
+    audio: A,
+    input: I,
+    sound_playing: bool,
+    // last pattern handed to the audio sink
+    audio_pattern: Option<AudioPattern>,
     speed: f32,
+    cpu_hz: u64,
+    display_hz: u64,
+    timing: TimingMode,
+    // VIP machine cycles left over from (or owed to) the previous frame
+    cycle_budget: i64,
+    // last key wait state handed to the video sink
+    waiting_for_key: bool,
+    save_slots: Option<SaveSlots>,
+    // one snapshot per displayed frame
+    rewind: Option<RewindBuffer>,
+    debugger: Option<Debugger>,
     emulator_state: EmulatorState,
 }
 
-impl Emulator {
-    pub fn init(speed: f32, scale: i32, sound_volume: f32, rom_path: &str) -> Result<Self, String> {
-        let sdl_context: Sdl = sdl2::init()?;
-        let video_subsystem = sdl_context.video()?;
-        let audio_subsystem = sdl_context.audio()?;
-        let event_pump = sdl_context.event_pump()?;
-        let window = Emulator::build_window(video_subsystem, scale as u32)?;
-
+impl<V: VideoSink, A: AudioSink, I: InputSource> Emulator<V, A, I> {
+    pub fn new(cpu: Chip8, video: V, audio: A, input: I, speed: f32) -> Self {
         let emulator_state: EmulatorState = EmulatorState::Playing;
-        let display: Display = Display::new(window, scale)?;
-        let keypad: Keypad = Keypad::new(event_pump);
-        let sound: Sound = Sound::new(audio_subsystem, sound_volume);
-        let mut cpu: Cpu = Cpu::new(display, keypad, sound);
-        cpu.init_load(rom_path);
 
-        Ok(Emulator { cpu, speed, emulator_state})
+        Emulator {
+            cpu,
+            video,
+            audio,
+            input,
+            sound_playing: false,
+            audio_pattern: None,
+            speed,
+            cpu_hz: CPU_HZ,
+            display_hz: DISPLAY_HZ,
+            timing: TIMING,
+            cycle_budget: 0,
+            waiting_for_key: false,
+            save_slots: None,
+            rewind: None,
+            debugger: None,
+            emulator_state,
+        }
     }
 
-    pub fn build_window(video_subsystem: VideoSubsystem, scale: u32) -> Result<Window, String> {
-        let window_width = WIDTH as u32 * scale;
-        let window_height = HEIGHT as u32 * scale;
+    /// Overrides the default `CPU_HZ` and `DISPLAY_HZ` rates.
+    pub fn with_clock(mut self, cpu_hz: u64, display_hz: u64) -> Self {
+        self.cpu_hz = cpu_hz;
+        self.display_hz = display_hz;
+        self
+    }
 
-        let window = video_subsystem
-            .window("CHIP-8 Emulator", window_width, window_height)
-            .position_centered()
-            .opengl()
-            .build()
-            .map_err(|e| e.to_string())?;
+    /// Selects how instructions are paced against the timers; the default is `TIMING`.
+    pub fn with_timing(mut self, timing: TimingMode) -> Self {
+        self.timing = timing;
+        self
+    }
 
-        println!("Created window of width {} and height {}", window_width, window_height);
-        Ok(window)
+    /// Enables the save and load state hotkeys, storing states in `save_slots`.
+    pub fn with_save_slots(mut self, save_slots: SaveSlots) -> Self {
+        self.save_slots = Some(save_slots);
+        self
     }
-    
+
+    /// Records every frame into `rewind` and steps back through it while the rewind key is held.
+    pub fn with_rewind(mut self, rewind: RewindBuffer) -> Self {
+        self.rewind = Some(rewind);
+        self
+    }
+
+    /// Lets the debug hotkey and [`break_into_debugger`](Self::break_into_debugger) open `debugger`.
+    pub fn with_debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    /// Stops at the debugger prompt before the next instruction, if there is a debugger.
+    pub fn break_into_debugger(&mut self) {
+        self.stop_in_debugger("Type help for a list of commands");
+    }
+
+    fn stop_in_debugger(&mut self, reason: &str) {
+        let Some(debugger) = &self.debugger else {
+            return;
+        };
+
+        debugger.print(&format!("{}\nStopped at {}", reason, debugger::location(&self.cpu)));
+        self.video.show_message("debugging");
+        if self.sound_playing {
+            self.audio.stop_sound();
+            self.sound_playing = false;
+        }
+        self.emulator_state = EmulatorState::Debugging;
+    }
+
+    pub fn cpu(&self) -> &Chip8 {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Chip8 {
+        &mut self.cpu
+    }
+
     pub fn main_loop(&mut self) {
         let mut last_cpu = Instant::now();
         let mut last_display = Instant::now();
 
-        let cpu_delta_t = 1000000.0 / (CPU_HZ as f32 * self.speed);
-        let display_delta_t = 1000000.0 / (DISPLAY_HZ as f32 * self.speed);
+        let cpu_delta_t = 1000000.0 / (self.cpu_hz as f32 * self.speed);
+        let display_delta_t = 1000000.0 / (self.display_hz as f32 * self.speed);
 
         'playing: loop {
             match self.emulator_state {
                 EmulatorState::Stopped => break 'playing,
                 EmulatorState::Paused => {
-                    match self.cpu.keypad.check_inputs() {
+                    match self.input.check_inputs(&mut self.cpu.keypad) {
                         EmulatorState::Paused => { self.emulator_state = EmulatorState::Playing }
                         EmulatorState::Stopped => break 'playing,
                         _ => {}
                     }
+                    // states can be saved and loaded while paused too
+                    self.handle_hotkeys();
                     continue 'playing;
                 }
-                _ => self.emulator_state = self.cpu.keypad.check_inputs()
+                EmulatorState::Debugging => {
+                    // keep the window alive and showing the current frame while the prompt waits
+                    if self.input.check_inputs(&mut self.cpu.keypad) == EmulatorState::Stopped {
+                        break 'playing;
+                    }
+                    self.handle_hotkeys();
+                    if self.debug_command() {
+                        break 'playing;
+                    }
+                    self.video.update_display(&self.cpu.display);
+                    self.video.render_canvas();
+
+                    sleep(Duration::from_millis(10));
+                    continue 'playing;
+                }
+                _ => {
+                    self.emulator_state = self.input.check_inputs(&mut self.cpu.keypad);
+                    self.input.check_second_keypad(&mut self.cpu.second_keypad);
+                }
             }
 
+            self.handle_hotkeys();
+
+            let halted = self.cpu.fault().is_some();
+            let rewinding = self.rewind.is_some() && self.input.rewind_held();
 
-            // run CPU cycle at CPU_HZ per second
-            if last_cpu.elapsed() >= Duration::from_micros(cpu_delta_t as u64) {
+            // run CPU cycle at cpu_hz per second
+            if self.timing == TimingMode::Fixed && !halted && !rewinding && last_cpu.elapsed() >= Duration::from_micros(cpu_delta_t as u64) {
                 last_cpu = Instant::now();
 
-                self.cpu.cycle();
+                match self.step() {
+                    Step::Exited => break 'playing,
+                    Step::Break => continue 'playing,
+                    Step::Ran => (),
+                }
             }
 
-            // update timers and display at DISPLAY_HZ per second
+            // update timers and display at display_hz per second
             if last_display.elapsed() >= Duration::from_micros(display_delta_t as u64) {
                 last_display = Instant::now();
 
-                self.cpu.update_timers();
-                self.cpu.display.update_display();
-                self.cpu.display.render_canvas();
+                match self.display_frame(rewinding) {
+                    Step::Exited => break 'playing,
+                    Step::Break => continue 'playing,
+                    Step::Ran => (),
+                }
             }
 
+            self.update_sound();
+            self.update_key_wait();
+
             sleep(Duration::from_micros(100));
         }
     }
 
-}
\ No newline at end of file
+    fn handle_hotkeys(&mut self) {
+        while let Some(hotkey) = self.input.take_hotkey() {
+            self.handle_hotkey(hotkey);
+        }
+    }
+
+    fn handle_hotkey(&mut self, hotkey: Hotkey) {
+        if hotkey == Hotkey::Debug {
+            if self.emulator_state != EmulatorState::Debugging {
+                self.break_into_debugger();
+            }
+            return;
+        }
+
+        let Some(save_slots) = &self.save_slots else {
+            return;
+        };
+
+        let message = match hotkey {
+            Hotkey::SaveState(slot) => match save_slots.save(slot, &self.cpu) {
+                Ok(path) => {
+                    info!("Saved state to {}", path.display());
+                    format!("saved slot {}", slot)
+                }
+                Err(err) => {
+                    warn!("Could not save slot {}: {}", slot, err);
+                    format!("could not save slot {}", slot)
+                }
+            },
+            Hotkey::LoadState(slot) => match save_slots.load(slot, &mut self.cpu) {
+                Ok(path) => {
+                    info!("Loaded state from {}", path.display());
+                    // show it straight away, even while paused
+                    self.video.update_display(&self.cpu.display);
+                    self.video.render_canvas();
+                    format!("loaded slot {}", slot)
+                }
+                Err(err) => {
+                    warn!("Could not load slot {}: {}", slot, err);
+                    format!("could not load slot {}: {}", slot, err)
+                }
+            },
+            Hotkey::Debug => return,
+        };
+        self.video.show_message(&message);
+    }
+
+    /// Runs the command typed at the debugger prompt, if any, returning true once the program has exited.
+    fn debug_command(&mut self) -> bool {
+        let Some(debugger) = &mut self.debugger else {
+            self.emulator_state = EmulatorState::Playing;
+            return false;
+        };
+        let Some(line) = debugger.poll() else {
+            return false;
+        };
+        if line.trim().is_empty() {
+            return false;
+        }
+
+        let output = match line.parse::<DebugCommand>() {
+            Ok(DebugCommand::Step(count)) => {
+                debugger.resume(&self.cpu);
+                for _ in 0..count {
+                    // a sprite waiting for the display interrupt would otherwise never finish
+                    if self.cpu.waiting_for_vblank() {
+                        self.cpu.update_timers();
+                    }
+                    match self.step() {
+                        Step::Exited => return true,
+                        // the hit has been reported already
+                        Step::Break => return false,
+                        Step::Ran => (),
+                    }
+                    if self.cpu.fault().is_some() {
+                        break;
+                    }
+                }
+                match self.cpu.fault() {
+                    Some(fault) => format!("CPU halted: {}", fault),
+                    None => debugger::location(&self.cpu),
+                }
+            }
+            Ok(DebugCommand::Continue) => {
+                debugger.resume(&self.cpu);
+                self.emulator_state = EmulatorState::Playing;
+                self.video.show_message("running");
+                return false;
+            }
+            Ok(DebugCommand::Break(Breakpoint::Memory { start, len }))
+                if start.saturating_add(len) > self.cpu.memory().len() =>
+            {
+                format!("{:#x}+{:#x} is outside the {:#x} bytes of memory", start, len, self.cpu.memory().len())
+            }
+            Ok(DebugCommand::Break(breakpoint)) => {
+                let id = debugger.breakpoints.add(breakpoint);
+                format!("added {}", debugger::describe_hit(id, breakpoint))
+            }
+            Ok(DebugCommand::Breakpoints) => debugger.breakpoints.list(),
+            Ok(DebugCommand::Delete(id)) => {
+                if debugger.breakpoints.remove(id) {
+                    format!("deleted {}", id)
+                } else {
+                    format!("there is no breakpoint or watchpoint {}", id)
+                }
+            }
+            Ok(DebugCommand::Registers) => debugger::registers(&self.cpu),
+            Ok(DebugCommand::Stack) => debugger::stack(&self.cpu),
+            Ok(DebugCommand::Memory { address, len }) => {
+                debugger::memory(&self.cpu, address, len).unwrap_or_else(|err| err)
+            }
+            Ok(DebugCommand::Set { register, value }) => match debugger::set(&mut self.cpu, register, value) {
+                Ok(()) if matches!(register, DebugRegister::Delay | DebugRegister::Sound) => debugger::timers(&self.cpu),
+                Ok(()) => debugger::registers(&self.cpu),
+                Err(err) => err,
+            },
+            Ok(DebugCommand::Timers) => debugger::timers(&self.cpu),
+            Ok(DebugCommand::Help) => String::from(debugger::HELP),
+            Err(err) => err,
+        };
+
+        if let Some(debugger) = &self.debugger {
+            debugger.print(&output);
+        }
+        false
+    }
+
+    /// One tick at `display_hz`: a step back through the rewind history, or the timers (and under
+    /// VIP timing the CPU) moving on a frame and the result being recorded. Either way the frame is drawn.
+    fn display_frame(&mut self, rewinding: bool) -> Step {
+        if rewinding {
+            self.rewind_frame();
+        } else {
+            let halted = self.cpu.fault().is_some();
+            if self.timing == TimingMode::Vip && !halted {
+                match self.run_vip_frame() {
+                    Step::Ran => (),
+                    stopped => return stopped,
+                }
+            }
+
+            // a halted machine keeps its last frame on screen but stops counting down
+            if !halted {
+                self.cpu.update_timers();
+            }
+            // nor is it recorded, so the history ends on the last frame that ran
+            if let Some(rewind) = self.rewind.as_mut().filter(|_| self.cpu.fault().is_none()) {
+                rewind.push(self.cpu.save_state());
+            }
+        }
+        self.video.update_display(&self.cpu.display);
+        self.video.render_canvas();
+        Step::Ran
+    }
+
+    // going back past a fault is allowed, so this is also how to recover from one
+    fn rewind_frame(&mut self) {
+        let Some(rewind) = &mut self.rewind else {
+            return;
+        };
+
+        // once the history runs out this stays on the oldest frame until the key is let go
+        while let Some(state) = rewind.rewind() {
+            match self.cpu.load_state(state) {
+                Ok(()) => break,
+                Err(err) => warn!("Skipping a rewind frame that could not be loaded: {}", err),
+            }
+        }
+    }
+
+    /// Runs one instruction, unless a breakpoint stops the emulator in the debugger first.
+    fn step(&mut self) -> Step {
+        let pc = self.cpu.pc();
+        if let Some((id, breakpoint)) = self.debugger.as_mut().and_then(|debugger| debugger.check_before(&self.cpu)) {
+            self.stop_in_debugger(&format!("Hit {}", debugger::describe_hit(id, breakpoint)));
+            return Step::Break;
+        }
+
+        if let Err(fault) = self.cpu.cycle() {
+            self.video.show_fault(&fault);
+        }
+
+        // 00FD ends the program like closing the window would
+        if self.cpu.exited() {
+            return Step::Exited;
+        }
+
+        if let Some((id, breakpoint)) = self.debugger.as_mut().and_then(|debugger| debugger.check_after(&self.cpu)) {
+            let hit = debugger::describe_hit(id, breakpoint);
+            self.stop_in_debugger(&format!("Hit {} after {}", hit, debugger::instruction_at(&self.cpu, pc)));
+            return Step::Break;
+        }
+        Step::Ran
+    }
+
+    /// Spends one frame's worth of VIP machine cycles, stopping early on a breakpoint or exit.
+    fn run_vip_frame(&mut self) -> Step {
+        // the display DMA and the timer interrupt take their share first
+        self.cycle_budget += (VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES) as i64;
+
+        while self.cycle_budget > 0 {
+            // a sprite waiting for the interrupt idles away the rest of the frame
+            if self.cpu.waiting_for_vblank() {
+                self.cycle_budget = 0;
+                break;
+            }
+            match self.step() {
+                Step::Ran => (),
+                stopped => return stopped,
+            }
+            if self.cpu.fault().is_some() {
+                break;
+            }
+            self.cycle_budget -= self.cpu.last_cycles().max(1) as i64;
+        }
+        Step::Ran
+    }
+
+    fn update_key_wait(&mut self) {
+        let waiting = self.cpu.waiting_for_key();
+        if waiting != self.waiting_for_key {
+            self.video.show_waiting_for_key(waiting);
+            self.waiting_for_key = waiting;
+        }
+    }
+
+    fn update_sound(&mut self) {
+        match self.cpu.take_sample_command() {
+            Some(SampleCommand::Play(sample)) => self.audio.play_sample(&sample),
+            Some(SampleCommand::Stop) => self.audio.stop_sample(),
+            None => (),
+        }
+
+        let pattern = self.cpu.audio_pattern();
+        if pattern != self.audio_pattern {
+            self.audio.set_pattern(pattern);
+            self.audio_pattern = pattern;
+        }
+
+        // the timers stop with a halted CPU, so the buzzer would otherwise sound until the window closes
+        let active = self.cpu.sound_active() && self.cpu.fault().is_none();
+        if active == self.sound_playing {
+            return;
+        }
+
+        if active {
+            self.audio.start_sound();
+        }
+        else {
+            self.audio.stop_sound();
+        }
+        self.sound_playing = active;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Headless;
+    use crate::error::FaultKind;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn rewinds_out_of_a_pc_fault() {
+        let rom = [
+            0x60, 0x03, // 0x200: V0 = 3
+            0xF0, 0x15, // 0x202: DT = V0
+            0xF0, 0x07, // 0x204: V0 = DT
+            0x30, 0x00, // 0x206: skip if V0 == 0
+            0x12, 0x04, // 0x208: jump 0x204
+            0x60, 0xFF, // 0x20A: V0 = 0xFF
+            0xBF, 0xFF, // 0x20C: jump 0xFFF + V0, past the end of memory
+        ];
+        let mut cpu = Chip8::new(Quirks::default());
+        cpu.init_load_bytes(&rom).unwrap();
+        let mut emulator = Emulator::new(cpu, Headless, Headless, Headless, 1.0)
+            .with_timing(TimingMode::Vip)
+            .with_rewind(RewindBuffer::new(60, 1 << 20));
+
+        for _ in 0..10 {
+            assert_eq!(emulator.display_frame(false), Step::Ran);
+        }
+        assert_eq!(emulator.cpu().fault().map(|fault| fault.kind), Some(FaultKind::PcOutOfRange));
+
+        assert_eq!(emulator.display_frame(true), Step::Ran);
+        assert_eq!(emulator.cpu().fault(), None);
+        assert!((0x200..0x20E).contains(&emulator.cpu().pc()));
+
+        // and runs on from there into the same fault
+        for _ in 0..10 {
+            emulator.display_frame(false);
+        }
+        assert_eq!(emulator.cpu().fault().map(|fault| fault.kind), Some(FaultKind::PcOutOfRange));
+    }
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can stop a ROM from starting.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The ROM file does not exist.
+    MissingRom { path: String },
+    /// The ROM file exists but could not be read.
+    UnreadableRom { path: String, source: io::Error },
+    /// The ROM does not fit between the load address and the end of memory.
+    RomTooLarge { size: usize, max: usize },
+    /// The ROM contains no bytes at all.
+    EmptyRom,
+    /// No audio device could be opened.
+    AudioUnavailable(String),
+    /// SDL, or the window it should have created, failed to initialise.
+    SdlInit(String),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::MissingRom { path } => write!(f, "ROM file '{}' does not exist", path),
+            Chip8Error::UnreadableRom { path, source } => write!(f, "could not read ROM file '{}': {}", path, source),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes but at most {} bytes fit in memory", size, max)
+            }
+            Chip8Error::EmptyRom => write!(f, "ROM is empty"),
+            Chip8Error::AudioUnavailable(err) => write!(f, "audio is unavailable: {}", err),
+            Chip8Error::SdlInit(err) => write!(f, "failed to initialise SDL: {}", err),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::UnreadableRom { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Why the CPU stopped executing a ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The program counter left addressable memory.
+    PcOutOfRange,
+    /// An instruction tried to read or write memory at or past the end through I.
+    IndexOutOfRange { i: usize },
+    /// A call nested deeper than the stack allows.
+    StackOverflow { depth: usize },
+    /// A return happened with nothing on the stack.
+    StackUnderflow,
+    /// The opcode is not part of the instruction set.
+    UnknownOpcode,
+}
+
+/// A fault raised by [`Cpu::cycle`](crate::components::Cpu::cycle), with the
+/// address and opcode of the instruction that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuFault {
+    pub kind: FaultKind,
+    pub pc: usize,
+    /// Missing when the opcode itself could not be fetched.
+    pub opcode: Option<u16>,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::PcOutOfRange => write!(f, "program counter out of range"),
+            FaultKind::IndexOutOfRange { i } => write!(f, "index register {:#06x} out of range", i),
+            FaultKind::StackOverflow { depth } => write!(f, "stack overflow (more than {} nested calls)", depth),
+            FaultKind::StackUnderflow => write!(f, "stack underflow (return without a call)"),
+            FaultKind::UnknownOpcode => write!(f, "unknown opcode"),
+        }
+    }
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at PC {:#06x}", self.kind, self.pc)?;
+        if let Some(opcode) = self.opcode {
+            write!(f, ", opcode {:#06x}", opcode)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CpuFault {}
@@ -0,0 +1,438 @@
+use std::{env, fs};
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use serde::Deserialize;
+
+use chip_chop::{KeyWaitMode, Platform, QuirkProfile, Quirks, TimingMode, UnknownOpcodePolicy};
+use chip_chop::components::{BIG_FONTS, FONTS};
+use chip_chop::config::{
+    CPU_HZ, DEFAULT_QUIRKS, DISPLAY_HZ, FONT_LOAD_START, KEY_WAIT, REWIND_BUDGET, REWIND_DEPTH, ROM_LOAD_START,
+    STACK_DEPTH, TIMING, UNKNOWN_OPCODES,
+};
+
+// defaults for anything the config file leaves out
+
+pub const ROM_PATH: &str = "src/roms";
+
+pub const SPEED: f32 = 1.0;
+pub const SCALE: i32 = 15;
+pub const VOLUME: f32 = 0.02;
+
+pub const FOREGROUND: Color = Color::RGB(255, 255, 255);
+pub const BACKGROUND: Color = Color::RGB(0, 0, 0);
+// XO-CHIP pixels set only in the second plane, and in both planes
+pub const PLANE2: Color = Color::RGB(170, 170, 170);
+pub const BLEND: Color = Color::RGB(85, 85, 85);
+
+pub const PAUSE_KEY: Keycode = Keycode::Space;
+pub const REWIND_KEY: Keycode = Keycode::Backspace;
+
+// not configurable: F1-F9 load save slots 1-9, with Shift held they save them
+pub const SLOT_KEYS: [Keycode; 9] = [
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+    Keycode::F9,
+];
+pub const DEBUG_KEY: Keycode = Keycode::F10;
+
+// assumes QWERTY keyboard is used
+pub const KEYMAPPINGS: [Keycode; 16] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Q,
+    Keycode::W,
+    Keycode::E,
+    Keycode::R,
+    Keycode::A,
+    Keycode::S,
+    Keycode::D,
+    Keycode::F,
+    Keycode::Z,
+    Keycode::X,
+    Keycode::C,
+    Keycode::V
+];
+
+const CONFIG_DIR: &str = "chip-chop";
+const CONFIG_FILE: &str = "config.toml";
+const SAVE_DIR: &str = "saves";
+
+/// Every user-adjustable setting, after merging the config file over the defaults above.
+pub struct Config {
+    pub rom_path: String,
+    pub save_dir: PathBuf,
+    pub cpu_hz: u64,
+    pub display_hz: u64,
+    pub timing: TimingMode,
+    pub speed: f32,
+    pub scale: i32,
+    pub volume: f32,
+    pub font_start: usize,
+    pub stack_depth: usize,
+    pub unknown_opcodes: UnknownOpcodePolicy,
+    pub key_wait: KeyWaitMode,
+    pub pause_key: Keycode,
+    pub rewind_key: Keycode,
+    // frames of history, 0 turns rewinding off
+    pub rewind_depth: usize,
+    // bytes
+    pub rewind_budget: usize,
+    pub keymappings: [Keycode; 16],
+    // the CHIP-8X second keypad, unmapped unless `keys2` is set
+    pub second_keymappings: Option<[Keycode; 16]>,
+    pub foreground: Color,
+    pub background: Color,
+    pub plane2: Color,
+    pub blend: Color,
+    pub quirk_profile: QuirkProfile,
+    pub quirk_overrides: QuirkSettings,
+    // only settable from the command line
+    pub trace_range: Option<RangeInclusive<usize>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rom_path: ROM_PATH.to_string(),
+            save_dir: default_save_dir(),
+            cpu_hz: CPU_HZ,
+            display_hz: DISPLAY_HZ,
+            timing: TIMING,
+            speed: SPEED,
+            scale: SCALE,
+            volume: VOLUME,
+            font_start: FONT_LOAD_START,
+            stack_depth: STACK_DEPTH,
+            unknown_opcodes: UNKNOWN_OPCODES,
+            key_wait: KEY_WAIT,
+            pause_key: PAUSE_KEY,
+            rewind_key: REWIND_KEY,
+            rewind_depth: REWIND_DEPTH,
+            rewind_budget: REWIND_BUDGET,
+            keymappings: KEYMAPPINGS,
+            second_keymappings: None,
+            foreground: FOREGROUND,
+            background: BACKGROUND,
+            plane2: PLANE2,
+            blend: BLEND,
+            quirk_profile: DEFAULT_QUIRKS,
+            quirk_overrides: QuirkSettings::default(),
+            trace_range: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    rom_path: Option<String>,
+    save_dir: Option<String>,
+    cpu_hz: Option<u64>,
+    display_hz: Option<u64>,
+    timing: Option<String>,
+    speed: Option<f32>,
+    scale: Option<i32>,
+    volume: Option<f32>,
+    font_start: Option<usize>,
+    stack_depth: Option<usize>,
+    unknown_opcodes: Option<String>,
+    key_wait: Option<String>,
+    pause_key: Option<String>,
+    rewind_key: Option<String>,
+    rewind_depth: Option<usize>,
+    // in KiB
+    rewind_budget: Option<usize>,
+    keys: Option<Vec<String>>,
+    keys2: Option<Vec<String>>,
+    colours: Option<ColoursFile>,
+    quirks: Option<QuirkSettings>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ColoursFile {
+    foreground: Option<String>,
+    background: Option<String>,
+    plane2: Option<String>,
+    blend: Option<String>,
+}
+
+/// The `[quirks]` table: a fallback profile plus individual flags forced on top of whichever profile a ROM ends up with.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuirkSettings {
+    profile: Option<String>,
+    pub shift: Option<bool>,
+    pub jump: Option<bool>,
+    pub load_store: Option<bool>,
+    pub vf_reset: Option<bool>,
+    pub clip: Option<bool>,
+    pub display_wait: Option<bool>,
+}
+
+impl QuirkSettings {
+    pub fn apply(&self, mut quirks: Quirks) -> Quirks {
+        quirks.shift = self.shift.unwrap_or(quirks.shift);
+        quirks.jump = self.jump.unwrap_or(quirks.jump);
+        quirks.load_store = self.load_store.unwrap_or(quirks.load_store);
+        quirks.vf_reset = self.vf_reset.unwrap_or(quirks.vf_reset);
+        quirks.clip = self.clip.unwrap_or(quirks.clip);
+        quirks.display_wait = self.display_wait.unwrap_or(quirks.display_wait);
+        quirks
+    }
+}
+
+impl Config {
+    /// Loads `path`, or the file in the XDG config directory if no path is given.
+    /// A missing XDG file just means defaults; a missing explicit file is an error.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let path: PathBuf = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Config::default()),
+            }
+        };
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("could not read config file {}: {}", path.display(), e))?;
+
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut config = Config::default();
+
+        if let Some(rom_path) = file.rom_path {
+            config.rom_path = rom_path;
+        }
+        if let Some(save_dir) = file.save_dir {
+            config.save_dir = PathBuf::from(save_dir);
+        }
+        if let Some(cpu_hz) = file.cpu_hz {
+            config.cpu_hz = positive("cpu_hz", cpu_hz)?;
+        }
+        if let Some(display_hz) = file.display_hz {
+            config.display_hz = positive("display_hz", display_hz)?;
+        }
+        if let Some(timing) = file.timing {
+            config.timing = timing.parse().map_err(|e| format!("`timing`: {}", e))?;
+        }
+        if let Some(speed) = file.speed {
+            if !(speed > 0.0 && speed.is_finite()) {
+                return Err(format!("`speed` must be a positive number, got {}", speed));
+            }
+            config.speed = speed;
+        }
+        if let Some(scale) = file.scale {
+            if scale <= 0 {
+                return Err(format!("`scale` must be a positive integer, got {}", scale));
+            }
+            config.scale = scale;
+        }
+        if let Some(volume) = file.volume {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(format!("`volume` must be between 0 and 1, got {}", volume));
+            }
+            config.volume = volume;
+        }
+        if let Some(font_start) = file.font_start {
+            let font_size = FONTS.len() + BIG_FONTS.len();
+            if font_start + font_size > ROM_LOAD_START {
+                return Err(format!(
+                    "`font_start` must leave room for the {}-byte fonts below {:#05x}, got {:#05x}",
+                    font_size, ROM_LOAD_START, font_start
+                ));
+            }
+            config.font_start = font_start;
+        }
+        if let Some(stack_depth) = file.stack_depth {
+            config.stack_depth = positive("stack_depth", stack_depth as u64)? as usize;
+        }
+        if let Some(unknown_opcodes) = file.unknown_opcodes {
+            config.unknown_opcodes = unknown_opcodes.parse().map_err(|e| format!("`unknown_opcodes`: {}", e))?;
+        }
+        if let Some(key_wait) = file.key_wait {
+            config.key_wait = key_wait.parse().map_err(|e| format!("`key_wait`: {}", e))?;
+        }
+        if let Some(pause_key) = file.pause_key {
+            config.pause_key = parse_key("pause_key", &pause_key)?;
+        }
+        if let Some(rewind_key) = file.rewind_key {
+            config.rewind_key = parse_key("rewind_key", &rewind_key)?;
+        }
+        if let Some(rewind_depth) = file.rewind_depth {
+            config.rewind_depth = rewind_depth;
+        }
+        if let Some(rewind_budget) = file.rewind_budget {
+            config.rewind_budget = positive("rewind_budget", rewind_budget as u64)? as usize * 1024;
+        }
+        if let Some(keys) = file.keys {
+            config.keymappings = parse_keymappings("keys", &keys)?;
+        }
+        if let Some(keys2) = file.keys2 {
+            let second_keymappings = parse_keymappings("keys2", &keys2)?;
+            for (i, key) in second_keymappings.iter().enumerate() {
+                if let Some(other) = config.keymappings.iter().position(|k| k == key) {
+                    return Err(format!("`keys2[{}]` is already mapped to CHIP-8 key {:X} in `keys`", i, other));
+                }
+                if *key == config.pause_key || *key == config.rewind_key {
+                    return Err(format!("`keys2[{}]` is already used as `pause_key` or `rewind_key`", i));
+                }
+            }
+            config.second_keymappings = Some(second_keymappings);
+        }
+        if let Some(key) = config.keymappings.iter().position(|&k| k == config.pause_key) {
+            return Err(format!("`pause_key` is already mapped to CHIP-8 key {:X} in `keys`", key));
+        }
+        if let Some(key) = config.keymappings.iter().position(|&k| k == config.rewind_key) {
+            return Err(format!("`rewind_key` is already mapped to CHIP-8 key {:X} in `keys`", key));
+        }
+        if config.rewind_key == config.pause_key {
+            return Err(String::from("`rewind_key` and `pause_key` must be different keys"));
+        }
+        for (slot, &key) in SLOT_KEYS.iter().enumerate() {
+            check_reserved(&config, key, &format!("save slot {}", slot + 1))?;
+        }
+        check_reserved(&config, DEBUG_KEY, "the debugger")?;
+        if let Some(colours) = file.colours {
+            if let Some(foreground) = colours.foreground {
+                config.foreground = parse_colour("colours.foreground", &foreground)?;
+            }
+            if let Some(background) = colours.background {
+                config.background = parse_colour("colours.background", &background)?;
+            }
+            if let Some(plane2) = colours.plane2 {
+                config.plane2 = parse_colour("colours.plane2", &plane2)?;
+            }
+            if let Some(blend) = colours.blend {
+                config.blend = parse_colour("colours.blend", &blend)?;
+            }
+        }
+        if let Some(quirks) = file.quirks {
+            if let Some(profile) = &quirks.profile {
+                config.quirk_profile = profile.parse().map_err(|e| format!("`quirks.profile`: {}", e))?;
+            }
+            config.quirk_overrides = quirks;
+        }
+
+        Ok(config)
+    }
+
+    /// The quirks to run `rom_path` with: the forced profile, else the ROM's own, else the configured one.
+    pub fn quirks_for(&self, forced: Option<QuirkProfile>, rom_path: &str) -> (QuirkProfile, Quirks) {
+        let profile: QuirkProfile = forced
+            .or_else(|| QuirkProfile::from_rom_path(rom_path))
+            .unwrap_or(self.quirk_profile);
+
+        (profile, self.quirk_overrides.apply(profile.quirks()))
+    }
+
+    /// The instruction set to run `rom_path` with: the forced one, else `profile`'s, which for
+    /// plain CHIP-8 can be narrowed down by the ROM's header.
+    pub fn platform_for(&self, forced: Option<Platform>, profile: QuirkProfile, rom_path: &str) -> Platform {
+        if let Some(platform) = forced {
+            return platform;
+        }
+        Platform::for_rom(profile, &read_header(rom_path).unwrap_or_default())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/chip-chop/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+/// `$XDG_DATA_HOME/chip-chop/saves`, falling back to `~/.local/share`, or `saves` if there's no home.
+pub fn default_save_dir() -> PathBuf {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")),
+    };
+    match data_home {
+        Some(dir) => dir.join(CONFIG_DIR).join(SAVE_DIR),
+        None => PathBuf::from(SAVE_DIR),
+    }
+}
+
+// an unreadable ROM is reported properly once the CPU tries to load it
+fn read_header(rom_path: &str) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    fs::File::open(rom_path).ok()?.take(2).read_to_end(&mut header).ok()?;
+    Some(header)
+}
+
+fn positive(key: &str, value: u64) -> Result<u64, String> {
+    if value == 0 {
+        return Err(format!("`{}` must be greater than 0", key));
+    }
+    Ok(value)
+}
+
+fn parse_key(key: &str, name: &str) -> Result<Keycode, String> {
+    Keycode::from_name(name).ok_or(format!("`{}`: unknown key name '{}'", key, name))
+}
+
+fn parse_keymappings(key: &str, names: &[String]) -> Result<[Keycode; 16], String> {
+    if names.len() != 16 {
+        return Err(format!("`{}` must list exactly 16 keys, one per CHIP-8 key 0-F, got {}", key, names.len()));
+    }
+
+    let mut keymappings: [Keycode; 16] = KEYMAPPINGS;
+    for (i, name) in names.iter().enumerate() {
+        let keycode = parse_key(&format!("{}[{}]", key, i), name)?;
+        if let Some(other) = keymappings[..i].iter().position(|&k| k == keycode) {
+            return Err(format!("`{}[{}]`: '{}' is already mapped to CHIP-8 key {:X}", key, i, name, other));
+        }
+        keymappings[i] = keycode;
+    }
+    Ok(keymappings)
+}
+
+// `hotkey` can't also be a keypad, pause or rewind key
+fn check_reserved(config: &Config, hotkey: Keycode, purpose: &str) -> Result<(), String> {
+    let reserved = |setting: String| format!("`{}`: {} is reserved for {}", setting, hotkey.name(), purpose);
+
+    if let Some(key) = config.keymappings.iter().position(|&k| k == hotkey) {
+        return Err(reserved(format!("keys[{}]", key)));
+    }
+    if let Some(key) = config.second_keymappings.iter().flatten().position(|&k| k == hotkey) {
+        return Err(reserved(format!("keys2[{}]", key)));
+    }
+    if config.pause_key == hotkey {
+        return Err(reserved(String::from("pause_key")));
+    }
+    if config.rewind_key == hotkey {
+        return Err(reserved(String::from("rewind_key")));
+    }
+    Ok(())
+}
+
+fn parse_colour(key: &str, value: &str) -> Result<Color, String> {
+    let invalid = || format!("`{}` must be a colour like \"#ff8800\", got '{}'", key, value);
+
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if hex.len() != 6 {
+        return Err(invalid());
+    }
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
@@ -0,0 +1,145 @@
+use sdl2::video::Window;
+use sdl2::render::Canvas;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use chip_chop::{CpuFault, Display};
+use chip_chop::backend::VideoSink;
+
+// the VP-590 colour board's foreground colours, indexed by zone colour
+const ZONE_COLOURS: [Color; 8] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(255, 0, 0),
+    Color::RGB(0, 0, 255),
+    Color::RGB(255, 0, 255),
+    Color::RGB(0, 255, 0),
+    Color::RGB(255, 255, 0),
+    Color::RGB(0, 255, 255),
+    Color::RGB(255, 255, 255),
+];
+
+// and the backgrounds 02A0 steps through
+const BACKGROUND_COLOURS: [Color; 4] = [
+    Color::RGB(0, 0, 128),
+    Color::RGB(0, 0, 0),
+    Color::RGB(0, 128, 0),
+    Color::RGB(128, 0, 0),
+];
+
+pub struct SdlDisplay {
+    canvas: Canvas<Window>,
+    // indexed by the pixel's plane bits: background, plane 1, plane 2, both
+    palette: [Color; 4],
+    // the last fault or status shown in the title, kept while FX0A comes and goes
+    message: Option<String>,
+    waiting_for_key: bool,
+}
+
+
+impl SdlDisplay {
+    pub fn new(window: Window, palette: [Color; 4]) -> Result<Self, String> {
+        let mut canvas: Canvas<Window> = window.into_canvas().build().map_err(|e| e.to_string())?;
+        
+        canvas.set_draw_color(palette[0]);
+        canvas.clear();
+        canvas.present();
+
+        Ok(SdlDisplay { canvas, palette, message: None, waiting_for_key: false })
+    }
+
+    fn update_title(&mut self) {
+        let mut title = String::from("CHIP-8 Emulator");
+        if let Some(message) = &self.message {
+            title.push_str(" - ");
+            title.push_str(message);
+        }
+        if self.waiting_for_key {
+            title.push_str(" - waiting for key");
+        }
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    // MEGA-CHIP mode: the already blended picture, faded by the screen alpha
+    fn draw_colours(&mut self, display: &Display) {
+        let alpha = display.alpha() as u32;
+        let channel = |colour: u32, shift: u32| (((colour >> shift) & 0xFF) * alpha / 0xFF) as u8;
+
+        for (i, row) in display.colours().chunks(display.width()).enumerate() {
+            for (j, &colour) in row.iter().enumerate() {
+                if colour & 0xFFFFFF != 0 {
+                    self.canvas.set_draw_color(Color::RGB(channel(colour, 16), channel(colour, 8), channel(colour, 0)));
+                    let _ = self.canvas.fill_rect(Rect::new(j as i32, i as i32, 1, 1));
+                }
+            }
+        }
+    }
+
+    // CHIP-8X: lit pixels take their zone's colour, the rest the background colour
+    fn draw_zones(&mut self, display: &Display) {
+        self.canvas.set_draw_color(BACKGROUND_COLOURS[display.background_colour() as usize]);
+        self.canvas.clear();
+
+        for (i, row) in display.pixels().chunks(display.width()).enumerate() {
+            for (j, &pixel) in row.iter().enumerate() {
+                if pixel != 0 {
+                    self.canvas.set_draw_color(ZONE_COLOURS[display.zone_colour(j, i) as usize]);
+                    let _ = self.canvas.fill_rect(Rect::new(j as i32, i as i32, 1, 1));
+                }
+            }
+        }
+    }
+}
+
+impl VideoSink for SdlDisplay {
+    fn update_display(&mut self, display: &Display) {
+        // draw in CHIP-8 pixels and let SDL scale them up to the window,
+        // which keeps the picture the same size when the resolution changes
+        let logical_size = (display.width() as u32, display.height() as u32);
+        if self.canvas.logical_size() != logical_size {
+            let _ = self.canvas.set_logical_size(logical_size.0, logical_size.1);
+        }
+
+        self.canvas.set_draw_color(self.palette[0]);
+        self.canvas.clear();
+
+        if display.megachip() {
+            self.draw_colours(display);
+            return;
+        }
+        if display.colour_zones() {
+            self.draw_zones(display);
+            return;
+        }
+
+        for (i, row) in display.pixels().chunks(display.width()).enumerate() {
+            for (j, &pixel) in row.iter().enumerate() {
+                if pixel != 0 {
+                    self.canvas.set_draw_color(self.palette[pixel as usize & 3]);
+                    let _ = self.canvas.fill_rect(Rect::new(j as i32, i as i32, 1, 1));
+                }
+            }
+        }
+    }
+
+    fn render_canvas(&mut self) {
+        self.canvas.present();
+    }
+
+    fn show_fault(&mut self, fault: &CpuFault) {
+        eprintln!("CPU halted: {}", fault);
+        self.message = Some(format!("halted: {}", fault));
+        self.update_title();
+    }
+
+    fn show_waiting_for_key(&mut self, waiting: bool) {
+        if waiting != self.waiting_for_key {
+            self.waiting_for_key = waiting;
+            self.update_title();
+        }
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+        self.update_title();
+    }
+}
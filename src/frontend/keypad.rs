@@ -0,0 +1,114 @@
+use sdl2::{keyboard::Keycode, EventPump};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Mod;
+
+use chip_chop::{EmulatorState, Keypad};
+use chip_chop::backend::{Hotkey, InputSource};
+
+use crate::frontend::config::{DEBUG_KEY, SLOT_KEYS};
+
+
+pub struct SdlKeypad {
+    event_pump: EventPump,
+    keymappings: [Keycode; 16],
+    // the CHIP-8X second keypad, if mapped
+    second_keymappings: Option<[Keycode; 16]>,
+    second_pressed: [bool; 16],
+    pause_key: Keycode,
+    rewind_key: Keycode,
+    rewind_held: bool,
+    hotkey: Option<Hotkey>,
+}
+
+impl SdlKeypad {
+    pub fn new(event_pump: EventPump, keymappings: [Keycode; 16], pause_key: Keycode, rewind_key: Keycode) -> Self {
+        SdlKeypad {
+            event_pump,
+            keymappings,
+            second_keymappings: None,
+            second_pressed: [false; 16],
+            pause_key,
+            rewind_key,
+            rewind_held: false,
+            hotkey: None,
+        }
+    }
+
+    /// Maps keys to the CHIP-8X second keypad read by EXF2 and EXF5.
+    pub fn with_second_keypad(mut self, keymappings: [Keycode; 16]) -> Self {
+        self.second_keymappings = Some(keymappings);
+        self
+    }
+}
+
+impl InputSource for SdlKeypad {
+    fn check_inputs(&mut self, keypad: &mut Keypad) -> EmulatorState {
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { 
+                    keycode: Some(Keycode::Escape), ..
+                } => return EmulatorState::Stopped,
+                Event::KeyDown { keycode: Some(key), keymod, repeat, ..} => {
+                    if key == self.pause_key {
+                        return EmulatorState::Paused
+                    }
+                    if key == self.rewind_key {
+                        self.rewind_held = true;
+                        continue;
+                    }
+
+                    if key == DEBUG_KEY {
+                        if !repeat {
+                            self.hotkey = Some(Hotkey::Debug);
+                        }
+                        continue;
+                    }
+                    if let Some(slot) = SLOT_KEYS.iter().position(|&k| k == key) {
+                        if !repeat {
+                            let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            self.hotkey = Some(if shift { Hotkey::SaveState(slot + 1) } else { Hotkey::LoadState(slot + 1) });
+                        }
+                        continue;
+                    }
+                    
+                    if !repeat {
+                        if let Some(i) = self.keymappings.iter().position(|&k| k == key) {
+                            keypad.set_pressed(i, true);
+                        }
+                        if let Some(i) = self.second_keymappings.and_then(|keys| keys.iter().position(|&k| k == key)) {
+                            self.second_pressed[i] = true;
+                        }
+                    }
+                }
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    if key == self.rewind_key {
+                        self.rewind_held = false;
+                    }
+                    if let Some(i) = self.keymappings.iter().position(|&k| k == key) {
+                        keypad.set_pressed(i, false);
+                    }
+                    if let Some(i) = self.second_keymappings.and_then(|keys| keys.iter().position(|&k| k == key)) {
+                        self.second_pressed[i] = false;
+                    }
+                }
+                Event::Window { win_event: WindowEvent::Close, .. } => {
+                    return EmulatorState::Stopped;
+                }
+                _ => {}
+            }
+        }
+        EmulatorState::Playing
+    }
+
+    fn take_hotkey(&mut self) -> Option<Hotkey> {
+        self.hotkey.take()
+    }
+
+    fn rewind_held(&self) -> bool {
+        self.rewind_held
+    }
+
+    fn check_second_keypad(&mut self, keypad: &mut Keypad) {
+        keypad.pressed = self.second_pressed;
+    }
+}
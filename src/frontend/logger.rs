@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use chip_chop::components::TRACE_TARGET;
+
+/// Writes log records to stderr or a file. The instruction trace is switched
+/// on separately from the level, so `--trace` alone doesn't also turn on the
+/// CPU's chattier trace-level messages.
+struct Logger {
+    level: LevelFilter,
+    trace: bool,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if metadata.target() == TRACE_TARGET {
+            return self.trace;
+        }
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = if record.target() == TRACE_TARGET {
+            writeln!(out, "{}", record.args())
+        } else {
+            writeln!(out, "[{:<5} {}] {}", record.level(), record.target(), record.args())
+        };
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap_or_else(|e| e.into_inner()).flush();
+    }
+}
+
+pub fn init(level: LevelFilter, trace: bool, log_file: Option<&str>) -> Result<(), String> {
+    let out: Box<dyn Write + Send> = match log_file {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("could not create log file {}: {}", path, e))?;
+            Box::new(BufWriter::new(file))
+        }
+        None => Box::new(io::stderr()),
+    };
+
+    let max_level = if trace { LevelFilter::Trace } else { level };
+    log::set_boxed_logger(Box::new(Logger { level, trace, out: Mutex::new(out) }))
+        .map_err(|e| e.to_string())?;
+    log::set_max_level(max_level);
+    Ok(())
+}
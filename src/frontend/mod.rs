@@ -0,0 +1,76 @@
+//! SDL2 window, audio and keyboard handling around the headless core.
+
+pub mod config;
+mod display;
+mod keypad;
+pub mod logger;
+mod sound;
+
+use log::debug;
+use sdl2::video::Window;
+use sdl2::{Sdl, VideoSubsystem};
+
+use chip_chop::{Chip8, Chip8Error, Debugger, Emulator, Platform, Quirks, RewindBuffer, SaveSlots, StdinLines};
+
+pub use config::Config;
+pub use display::SdlDisplay;
+pub use keypad::SdlKeypad;
+pub use sound::SdlSound;
+
+pub type SdlEmulator = Emulator<SdlDisplay, SdlSound, SdlKeypad>;
+
+/// Builds an emulator for `rom_path` whose debugger reads from `stdin`.
+pub fn init(config: &Config, platform: Platform, quirks: Quirks, rom_path: &str, stdin: &StdinLines) -> Result<SdlEmulator, Chip8Error> {
+    // load the ROM first so a bad one never opens a window
+    let mut cpu: Chip8 = Chip8::new(quirks)
+        .with_platform(platform)
+        .with_font_start(config.font_start)
+        .with_stack_depth(config.stack_depth)
+        .with_unknown_opcode_policy(config.unknown_opcodes)
+        .with_key_wait_mode(config.key_wait);
+    if let Some(range) = &config.trace_range {
+        cpu = cpu.with_trace_range(range.clone());
+    }
+    cpu.init_load(rom_path)?;
+
+    let sdl_context: Sdl = sdl2::init().map_err(Chip8Error::SdlInit)?;
+    let video_subsystem = sdl_context.video().map_err(Chip8Error::SdlInit)?;
+    let audio_subsystem = sdl_context.audio().map_err(Chip8Error::AudioUnavailable)?;
+    let event_pump = sdl_context.event_pump().map_err(Chip8Error::SdlInit)?;
+    let window = build_window(video_subsystem, config.scale as u32, platform).map_err(Chip8Error::SdlInit)?;
+
+    let display: SdlDisplay = SdlDisplay::new(window, [config.background, config.foreground, config.plane2, config.blend])
+        .map_err(Chip8Error::SdlInit)?;
+    let mut keypad: SdlKeypad = SdlKeypad::new(event_pump, config.keymappings, config.pause_key, config.rewind_key);
+    if let Some(second_keymappings) = config.second_keymappings {
+        keypad = keypad.with_second_keypad(second_keymappings);
+    }
+    let sound: SdlSound = SdlSound::new(audio_subsystem, config.volume)?;
+
+    let mut emulator = Emulator::new(cpu, display, sound, keypad, config.speed)
+        .with_clock(config.cpu_hz, config.display_hz)
+        .with_timing(config.timing)
+        .with_save_slots(SaveSlots::new(&config.save_dir, rom_path))
+        .with_debugger(Debugger::new(stdin.clone()));
+    if config.rewind_depth > 0 {
+        emulator = emulator.with_rewind(RewindBuffer::new(config.rewind_depth, config.rewind_budget));
+    }
+    Ok(emulator)
+}
+
+/// Opens a window `scale` times the size of `platform`'s starting resolution.
+pub fn build_window(video_subsystem: VideoSubsystem, scale: u32, platform: Platform) -> Result<Window, String> {
+    let (width, height) = platform.screen_size();
+    let window_width = width as u32 * scale;
+    let window_height = height as u32 * scale;
+
+    let window = video_subsystem
+        .window("CHIP-8 Emulator", window_width, window_height)
+        .position_centered()
+        .opengl()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    debug!("Created window of width {} and height {}", window_width, window_height);
+    Ok(window)
+}
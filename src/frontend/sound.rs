@@ -0,0 +1,149 @@
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioSpecDesired, AudioDevice};
+
+use chip_chop::{AudioPattern, Chip8Error};
+use chip_chop::components::{Sample, PATTERN_SIZE};
+use chip_chop::backend::AudioSink;
+
+// the classic CHIP-8 buzzer
+const BUZZER_HZ: f32 = 440.0;
+const PATTERN_BITS: f32 = (PATTERN_SIZE * 8) as f32;
+
+pub struct SdlSound {
+    device: AudioDevice<Beeper>,
+}
+
+impl SdlSound {
+    pub fn new(audio_subsystem: AudioSubsystem, sound_volume: f32) -> Result<Self, Chip8Error> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None
+        };
+        let device: AudioDevice<Beeper> = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            Beeper {
+                sample_rate: spec.freq as f32,
+                phase: 0.0,
+                volume: sound_volume,
+                pattern: None,
+                buzzing: false,
+                sample: None,
+                sample_position: 0.0,
+            }
+        }).map_err(Chip8Error::AudioUnavailable)?;
+
+        Ok(SdlSound { device })
+    }
+}
+
+impl SdlSound {
+    // keeps the device running while either the buzzer or a sample has something to play
+    fn update_device(&mut self) {
+        let playing = {
+            let beeper = self.device.lock();
+            beeper.buzzing || beeper.sample.is_some()
+        };
+
+        if playing {
+            self.device.resume()
+        } else {
+            self.device.pause()
+        }
+    }
+}
+
+impl AudioSink for SdlSound {
+    fn start_sound(&mut self) {
+        self.device.lock().buzzing = true;
+        self.update_device();
+    }
+
+    fn stop_sound(&mut self) {
+        self.device.lock().buzzing = false;
+        self.update_device();
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        let mut beeper = self.device.lock();
+        beeper.pattern = pattern;
+        beeper.phase = 0.0;
+    }
+
+    fn play_sample(&mut self, sample: &Sample) {
+        {
+            let mut beeper = self.device.lock();
+            beeper.sample = Some(sample.clone());
+            beeper.sample_position = 0.0;
+        }
+        self.update_device();
+    }
+
+    fn stop_sample(&mut self) {
+        self.device.lock().sample = None;
+        self.update_device();
+    }
+}
+
+/// Plays a 440 Hz square wave, or loops an XO-CHIP pattern once one is loaded,
+/// mixed with any MEGA-CHIP sample.
+struct Beeper {
+    sample_rate: f32,
+    // position within one period of the square wave, or within the 128-bit pattern
+    phase: f32,
+    volume: f32,
+    pattern: Option<AudioPattern>,
+    // whether the sound timer is running
+    buzzing: bool,
+    sample: Option<Sample>,
+    // index into the sample's data, advancing at its own rate
+    sample_position: f32,
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.buzzing { self.buzzer() } else { 0.0 };
+            *x += self.next_sample();
+        }
+    }
+}
+
+impl Beeper {
+    fn buzzer(&mut self) -> f32 {
+        let high = match &self.pattern {
+            Some(pattern) => {
+                let high = pattern.bit(self.phase as usize);
+                self.phase = (self.phase + pattern.playback_rate() / self.sample_rate) % PATTERN_BITS;
+                high
+            }
+            None => {
+                let high = self.phase <= 0.5;
+                self.phase = (self.phase + BUZZER_HZ / self.sample_rate) % 1.0;
+                high
+            }
+        };
+
+        if high { self.volume } else { -self.volume }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let Some(sample) = &self.sample else {
+            return 0.0;
+        };
+
+        let position = self.sample_position as usize;
+        if position >= sample.data.len() {
+            if sample.looping && !sample.data.is_empty() {
+                self.sample_position = 0.0;
+                return self.next_sample();
+            }
+            self.sample = None;
+            return 0.0;
+        }
+
+        self.sample_position += sample.rate as f32 / self.sample_rate;
+        (sample.data[position] as f32 - 128.0) / 128.0 * self.volume
+    }
+}
\ No newline at end of file
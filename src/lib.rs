@@ -0,0 +1,38 @@
+//! Headless CHIP-8 emulation core.
+//!
+//! The machine in here knows nothing about windows, audio devices or
+//! keyboards: frontends implement the traits in [`backend`] and hand them
+//! to an [`Emulator`], or drive a [`Chip8`] directly by feeding key presses
+//! into [`Keypad`], reading the framebuffer out of [`Display`] and polling
+//! [`Chip8::sound_active`] for the buzzer (and [`Chip8::audio_pattern`]
+//! for what it should sound like).
+
+extern crate log;
+extern crate rand;
+
+pub mod args;
+pub mod backend;
+pub mod components;
+pub mod config;
+pub mod debugger;
+pub mod disassembler;
+pub mod emulator;
+pub mod error;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod savestate;
+pub mod timing;
+
+pub use components::{AudioPattern, Display, KeyWaitMode, Keypad, UnknownOpcodePolicy, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, TWO_PAGE_HEIGHT, WIDTH};
+pub use debugger::{DebugCommand, Debugger, StdinLines};
+pub use emulator::{Emulator, EmulatorState};
+pub use error::{Chip8Error, CpuFault, FaultKind};
+pub use platform::Platform;
+pub use quirks::{QuirkProfile, Quirks};
+pub use rewind::RewindBuffer;
+pub use savestate::{SaveSlots, SaveStateError};
+pub use timing::TimingMode;
+
+/// A complete CHIP-8 machine: memory, registers, timers, framebuffer and keypad state.
+pub use components::Cpu as Chip8;
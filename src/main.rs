@@ -1,38 +1,20 @@
 extern crate sdl2;
-extern crate rand;
 
-mod emulator;
-mod components;
-mod config;
+mod cli;
+mod frontend;
 
-use std::{io, fs};
-use crate::{config::ROM_PATH, emulator::Emulator};
+use std::{env, io, fs};
+use std::collections::BTreeMap;
+use std::process::ExitCode;
 
-fn ask_for_input<T: std::str::FromStr>(variable: &mut T, msg: &str) {
-    loop {
-        println!("{} (or nothing for default): ", msg);
+use chip_chop::{Chip8Error, StdinLines};
+use log::LevelFilter;
 
-        let mut input_string = String::new();
-        io::stdin().read_line(&mut input_string).expect("Failed to read line");
+use crate::cli::{Command, Options, USAGE};
+use crate::frontend::{Config, SdlEmulator};
 
-        if input_string.trim().is_empty() {
-            return;
-        }
-
-        match input_string.trim().parse::<T>() {
-            Ok(value) => {
-                *variable = value;
-                return;
-            }
-            Err(_) => {
-                println!("Input is invalid. Please enter a valid input!");
-            }
-        }
-    }
-}
-
-fn list_rom_files() -> Result<Vec<String>, io::Error> {
-    let entries = fs::read_dir("src/roms")?;
+fn list_rom_files(rom_dir: &str) -> Result<Vec<String>, io::Error> {
+    let entries = fs::read_dir(rom_dir)?;
 
     let mut files: Vec<String> = Vec::new();
 
@@ -47,11 +29,11 @@ fn list_rom_files() -> Result<Vec<String>, io::Error> {
     Ok(files)
 }
 
-fn select_file(files: &[String]) -> Option<String> {
+fn select_file(files: &[String], stdin: &StdinLines) -> Option<String> {
     println!("Enter the number of the ROM you want to select (or type 'esc' to quit):");
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).expect("Failed to read line");
+    // a closed stdin quits like 'esc' does
+    let input = stdin.read_line().unwrap_or_else(|| String::from("esc"));
 
     if input.trim().to_lowercase() == "esc" {
         return Some(String::from(""))
@@ -68,45 +50,100 @@ fn select_file(files: &[String]) -> Option<String> {
     }
 }
 
-pub fn main() -> Result<(), String> {
-    println!("Welcome to CHIP-8 Emulator!");
+fn play(config: &Config, options: &Options, rom_path: &str, stdin: &StdinLines) -> Result<(), Chip8Error> {
+    let (profile, quirks) = config.quirks_for(options.quirks, rom_path);
+    let platform = config.platform_for(options.platform, profile, rom_path);
+
+    println!("Playing ROM at {} as {} with {} quirks", rom_path, platform, profile);
+    let mut emulator: SdlEmulator = frontend::init(config, platform, quirks, rom_path, stdin)?;
+    if options.debug {
+        emulator.break_into_debugger();
+    }
+    emulator.main_loop();
+
+    print_unknown_opcodes(emulator.cpu().unknown_opcodes());
+    Ok(())
+}
+
+// hints at which platform the ROM really needs
+fn print_unknown_opcodes(unknown_opcodes: &BTreeMap<u16, usize>) {
+    if unknown_opcodes.is_empty() {
+        return;
+    }
 
-    let mut speed: f32 = 1.0;
-    ask_for_input(&mut speed, "Please enter a floating point number for GAME SPEED");
-    let mut scale: i32 = 15;
-    ask_for_input(&mut scale, "Please enter an integer for WINDOW SCALE");
-    let mut sound_volume: f32 = 0.02;
-    ask_for_input(&mut sound_volume, "Please enter a floating point number for GAME VOLUME");
+    println!("Unknown opcodes encountered:");
+    for (opcode, count) in unknown_opcodes {
+        println!("  {:#06x} x {}", opcode, count);
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    frontend::logger::init(options.log_level.unwrap_or(LevelFilter::Warn), options.trace, options.log_file.as_deref())?;
+
+    let mut config: Config = Config::load(options.config_path.as_deref())?;
+    options.override_config(&mut config);
+
+    // the ROM picker and the debugger take turns reading stdin through one thread
+    let stdin = StdinLines::spawn();
+
+    if let Some(rom_path) = &options.rom_path {
+        return play(&config, &options, rom_path, &stdin).map_err(|e| e.to_string());
+    }
+
+    println!("Welcome to CHIP-8 Emulator!");
 
     'main: loop {
-        let mut rom_path: String = ROM_PATH.to_string();
-        while rom_path == ROM_PATH {
-            match list_rom_files() {
+        let mut rom_path: String = config.rom_path.clone();
+        while rom_path == config.rom_path {
+            match list_rom_files(&config.rom_path) {
                 Ok(files) => {
-                    match select_file(&files) {
+                    match select_file(&files, &stdin) {
                         Some(selected) => {
                             if selected.is_empty() {
                                 break 'main;
                             }
                             rom_path.push('/');
-                            rom_path.push_str(&*selected);
+                            rom_path.push_str(&selected);
                         },
                         _ => println!("No ROM selected!")
                     }
                 }
                 Err(err) => { 
-                    eprintln!("Error listing ROMS {}", err);
-                    break 'main
+                    return Err(format!("Error listing ROMS {}", err));
                 }
             }
         }
 
-        println!("Playing ROM at {}", rom_path);
-        let mut emulator: Emulator = Emulator::init(speed, scale, sound_volume, &*rom_path)?;
-        emulator.main_loop();
+        // a ROM that fails to start sends you back to the menu
+        if let Err(err) = play(&config, &options, &rom_path, &stdin) {
+            eprintln!("Could not play {}: {}", rom_path, err);
+        }
     }
 
     Ok(())
 }
 
+pub fn main() -> ExitCode {
+    let options: Options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = run(options);
+    log::logger().flush();
 
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
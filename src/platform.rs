@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::components::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, MEGA_HEIGHT, MEGA_WIDTH, TWO_PAGE_HEIGHT, WIDTH};
+use crate::config::ROM_LOAD_START;
+use crate::quirks::QuirkProfile;
+
+// every two-page hires ROM opens with a jump over the interpreter patch that follows it
+const TWO_PAGE_HEADER: [u8; 2] = [0x12, 0x60];
+
+// the CHIP-8X interpreter is larger and pushes programs up a page
+const CHIP_8X_LOAD_START: usize = 0x300;
+
+/// The instruction set a ROM was written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original COSMAC VIP interpreter, and CHIP-48 which kept its instruction set.
+    Chip8,
+    /// The VIP's two-page "CHIP-8 hires" interpreter: a 64x64 screen, with programs
+    /// starting at 0x260 behind a `1260` header.
+    Chip8Hires,
+    /// CHIP-8X for the VIP's VP-590 colour board: colour zones, a background colour,
+    /// a second keypad and an I/O port.
+    Chip8X,
+    /// SUPER-CHIP 1.1: high resolution, scrolling, 16x16 sprites, big font and RPL flags.
+    SuperChip,
+    /// XO-CHIP, Octo's extension of SUPER-CHIP.
+    XoChip,
+    /// MEGA-CHIP: SUPER-CHIP plus a 256x192 palette mode and digitised sound.
+    MegaChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 6] = [
+        Platform::Chip8,
+        Platform::Chip8Hires,
+        Platform::Chip8X,
+        Platform::SuperChip,
+        Platform::XoChip,
+        Platform::MegaChip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::Chip8Hires => "CHIP-8 HIRES",
+            Platform::Chip8X => "CHIP-8X",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+            Platform::MegaChip => "MEGA-CHIP",
+        }
+    }
+
+    /// The platform a ROM needs judging by its contents, if they give it away.
+    pub fn from_rom_header(rom: &[u8]) -> Option<Platform> {
+        if rom.starts_with(&TWO_PAGE_HEADER) {
+            return Some(Platform::Chip8Hires);
+        }
+        None
+    }
+
+    /// The platform to run `rom` with under `profile`: the profile's own, except that plain
+    /// CHIP-8 can be narrowed down by the ROM's header. Only the first bytes of `rom` are needed.
+    pub fn for_rom(profile: QuirkProfile, rom: &[u8]) -> Platform {
+        match profile.platform() {
+            Platform::Chip8 => Platform::from_rom_header(rom).unwrap_or(Platform::Chip8),
+            platform => platform,
+        }
+    }
+
+    /// The resolution the screen starts at.
+    pub fn screen_size(self) -> (usize, usize) {
+        match self {
+            Platform::Chip8Hires => (WIDTH, TWO_PAGE_HEIGHT),
+            _ => (WIDTH, HEIGHT),
+        }
+    }
+
+    /// Whether the screen can be `width` x `height`, at start or after switching modes.
+    pub fn has_resolution(self, width: usize, height: usize) -> bool {
+        (width, height) == self.screen_size()
+            || (self.superchip() && (width, height) == (HIRES_WIDTH, HIRES_HEIGHT))
+            || (self.megachip() && (width, height) == (MEGA_WIDTH, MEGA_HEIGHT))
+    }
+
+    /// Where the ROM is loaded and execution starts.
+    pub fn rom_start(self) -> usize {
+        match self {
+            Platform::Chip8X => CHIP_8X_LOAD_START,
+            _ => ROM_LOAD_START,
+        }
+    }
+
+    /// Whether the display has CHIP-8X's colour zones and background colour.
+    pub fn chip8x(self) -> bool {
+        self == Platform::Chip8X
+    }
+
+    /// Bytes of addressable memory.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => 0x10000,
+            Platform::MegaChip => 0x1000000,
+            _ => 0x1000,
+        }
+    }
+
+    /// Whether the SUPER-CHIP 1.1 opcodes are available.
+    pub fn superchip(self) -> bool {
+        matches!(self, Platform::SuperChip | Platform::XoChip | Platform::MegaChip)
+    }
+
+    /// Whether the XO-CHIP opcodes are available.
+    pub fn xochip(self) -> bool {
+        self == Platform::XoChip
+    }
+
+    /// Whether the MEGA-CHIP opcodes in the 0x0 group are available.
+    pub fn megachip(self) -> bool {
+        self == Platform::MegaChip
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "hires" | "chip8-hires" | "chip-8-hires" => Ok(Platform::Chip8Hires),
+            "chip8x" | "chip-8x" => Ok(Platform::Chip8X),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            "megachip" | "mega-chip" => Ok(Platform::MegaChip),
+            _ => Err(format!("unknown platform '{}' (expected one of chip8, hires, chip8x, schip, xochip, megachip)", s)),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Behavioural differences between CHIP-8 interpreters.
+//!
+//! Each field toggles one well-known incompatibility; the associated
+//! constants bundle them into the profiles of the common platforms.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::DEFAULT_QUIRKS;
+use crate::platform::Platform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place instead of shifting VY into VX.
+    pub shift: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    /// FX55/FX65 leave I pointing past the last register they touched.
+    pub load_store: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip: bool,
+    /// DXYN waits for the next 60 Hz tick, limiting draws to one per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// What most present-day interpreters do, and what this emulator did before it had profiles.
+    pub const MODERN: Quirks = Quirks {
+        shift: true,
+        jump: false,
+        load_store: false,
+        vf_reset: false,
+        clip: false,
+        display_wait: false,
+    };
+
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        jump: false,
+        load_store: true,
+        vf_reset: true,
+        clip: true,
+        display_wait: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        jump: true,
+        load_store: false,
+        vf_reset: false,
+        clip: true,
+        display_wait: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        jump: true,
+        load_store: false,
+        vf_reset: false,
+        clip: true,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        jump: false,
+        load_store: true,
+        vf_reset: false,
+        clip: false,
+        display_wait: false,
+    };
+
+    pub const MEGA_CHIP: Quirks = Quirks {
+        shift: true,
+        jump: true,
+        load_store: false,
+        vf_reset: false,
+        clip: true,
+        display_wait: false,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        DEFAULT_QUIRKS.quirks()
+    }
+}
+
+/// A named set of quirks, selectable by name or by ROM file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirkProfile {
+    Modern,
+    CosmacVip,
+    Chip8X,
+    Chip48,
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl QuirkProfile {
+    pub const ALL: [QuirkProfile; 7] = [
+        QuirkProfile::Modern,
+        QuirkProfile::CosmacVip,
+        QuirkProfile::Chip8X,
+        QuirkProfile::Chip48,
+        QuirkProfile::SuperChip,
+        QuirkProfile::XoChip,
+        QuirkProfile::MegaChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirkProfile::Modern => Quirks::MODERN,
+            // CHIP-8X is a patched VIP interpreter and keeps its behaviour
+            QuirkProfile::CosmacVip | QuirkProfile::Chip8X => Quirks::COSMAC_VIP,
+            QuirkProfile::Chip48 => Quirks::CHIP_48,
+            QuirkProfile::SuperChip => Quirks::SUPER_CHIP,
+            QuirkProfile::XoChip => Quirks::XO_CHIP,
+            QuirkProfile::MegaChip => Quirks::MEGA_CHIP,
+        }
+    }
+
+    pub fn platform(self) -> Platform {
+        match self {
+            QuirkProfile::Modern | QuirkProfile::CosmacVip | QuirkProfile::Chip48 => Platform::Chip8,
+            QuirkProfile::Chip8X => Platform::Chip8X,
+            QuirkProfile::SuperChip => Platform::SuperChip,
+            QuirkProfile::XoChip => Platform::XoChip,
+            QuirkProfile::MegaChip => Platform::MegaChip,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirkProfile::Modern => "modern",
+            QuirkProfile::CosmacVip => "vip",
+            QuirkProfile::Chip8X => "chip8x",
+            QuirkProfile::Chip48 => "chip48",
+            QuirkProfile::SuperChip => "schip",
+            QuirkProfile::XoChip => "xochip",
+            QuirkProfile::MegaChip => "megachip",
+        }
+    }
+
+    /// Guesses the profile from the ROM's extension, using the conventions of the Octo archive.
+    /// Plain `.ch8` says nothing about which interpreter a ROM was written for, so it gives None.
+    pub fn from_rom_path(rom_path: &str) -> Option<QuirkProfile> {
+        let extension = Path::new(rom_path).extension()?.to_str()?.to_lowercase();
+        match &*extension {
+            "c8x" => Some(QuirkProfile::Chip8X),
+            "sc8" => Some(QuirkProfile::SuperChip),
+            "xo8" => Some(QuirkProfile::XoChip),
+            "mc8" => Some(QuirkProfile::MegaChip),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for QuirkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QuirkProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "modern" => Ok(QuirkProfile::Modern),
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Ok(QuirkProfile::CosmacVip),
+            "chip8x" | "chip-8x" => Ok(QuirkProfile::Chip8X),
+            "chip48" | "chip-48" => Ok(QuirkProfile::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(QuirkProfile::SuperChip),
+            "xochip" | "xo-chip" => Ok(QuirkProfile::XoChip),
+            "megachip" | "mega-chip" => Ok(QuirkProfile::MegaChip),
+            _ => Err(format!("unknown quirk profile '{}' (expected one of modern, vip, chip8x, chip48, schip, xochip, megachip)", s)),
+        }
+    }
+}
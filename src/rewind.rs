@@ -0,0 +1,120 @@
+//! Recent history for rewinding gameplay.
+//!
+//! Only the newest snapshot is kept whole. Every older one is stored as the
+//! bytes that differ from the snapshot after it. Between two frames little
+//! more than the registers, timers and a few bytes of memory change, so a
+//! frame of history usually costs tens of bytes instead of a full save state.
+
+use std::collections::VecDeque;
+
+// equal bytes shorter than this don't split a run, since each run costs 8 bytes of header
+const MERGE_GAP: usize = 8;
+
+/// A ring buffer of save states, newest last.
+pub struct RewindBuffer {
+    depth: usize,
+    budget: usize,
+    latest: Option<Vec<u8>>,
+    // deltas[i] turns snapshot i + 1 back into snapshot i
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    /// Keeps up to `depth` steps of history in at most `budget` bytes of deltas, dropping the
+    /// oldest first. The newest snapshot is held on top of the budget, however large it is.
+    pub fn new(depth: usize, budget: usize) -> Self {
+        RewindBuffer { depth, budget, latest: None, deltas: VecDeque::new(), delta_bytes: 0 }
+    }
+
+    /// How many steps back can be taken.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes currently held, snapshot and deltas together.
+    pub fn memory(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    /// Records `state` as the newest snapshot.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            let delta = diff(&state, &latest);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+
+        while !self.deltas.is_empty() && (self.deltas.len() > self.depth || self.delta_bytes > self.budget) {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.delta_bytes -= oldest.len();
+            }
+        }
+    }
+
+    /// Steps one snapshot back, returning it, or None once the history is used up.
+    pub fn rewind(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.delta_bytes -= delta.len();
+
+        let latest = self.latest.as_mut()?;
+        apply(latest, &delta);
+        Some(latest)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+}
+
+/// Encodes how to turn `from` into `to`: `to`'s length, then every run of
+/// differing bytes as its offset, its length and `to`'s bytes.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    delta.extend_from_slice(&(to.len() as u32).to_le_bytes());
+
+    let differs = |i: usize| from.get(i) != Some(&to[i]);
+
+    let mut i = 0;
+    while i < to.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut last_diff = i;
+        while i < to.len() && i - last_diff <= MERGE_GAP {
+            if differs(i) {
+                last_diff = i;
+            }
+            i += 1;
+        }
+
+        delta.extend_from_slice(&(start as u32).to_le_bytes());
+        delta.extend_from_slice(&((last_diff + 1 - start) as u32).to_le_bytes());
+        delta.extend_from_slice(&to[start..=last_diff]);
+    }
+    delta
+}
+
+fn apply(state: &mut Vec<u8>, delta: &[u8]) {
+    let read = |at: usize| u32::from_le_bytes([delta[at], delta[at + 1], delta[at + 2], delta[at + 3]]) as usize;
+
+    state.resize(read(0), 0);
+
+    let mut at = 4;
+    while at < delta.len() {
+        let (start, len) = (read(at), read(at + 4));
+        at += 8;
+        state[start..start + len].copy_from_slice(&delta[at..at + len]);
+        at += len;
+    }
+}
@@ -0,0 +1,236 @@
+//! Versioned snapshots of the whole machine.
+//!
+//! A save state is a small header (magic, format version and a hash of the
+//! ROM it was taken from) followed by the CPU, display and keypad state in a
+//! fixed little-endian layout. [`SaveSlots`] keeps numbered states on disk.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Chip8;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// Bumped whenever the layout changes; older states are rejected rather than misread.
+pub const VERSION: u16 = 1;
+
+/// Why a save state could not be written or restored.
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// Reading or writing the state file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The file doesn't start with the save state header.
+    NotASaveState,
+    /// The state was written by another version of the format.
+    UnsupportedVersion(u16),
+    /// The state was taken while a different ROM was loaded.
+    DifferentRom,
+    /// The state ends early or holds values the machine can't take.
+    Corrupt,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io { path, source } => write!(f, "could not access save state '{}': {}", path.display(), source),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "save state format version {} is not supported (expected {})", version, VERSION)
+            }
+            SaveStateError::DifferentRom => write!(f, "save state belongs to a different ROM"),
+            SaveStateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveStateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 64-bit FNV-1a, enough to tell ROMs apart without pulling in a hashing crate.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Numbered save states for one ROM, stored as `<dir>/<rom name>.<slot>.state`.
+pub struct SaveSlots {
+    dir: PathBuf,
+    rom_name: String,
+}
+
+impl SaveSlots {
+    pub fn new(dir: impl Into<PathBuf>, rom_path: &str) -> Self {
+        let rom_name = Path::new(rom_path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("rom"));
+
+        SaveSlots { dir: dir.into(), rom_name }
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}.state", self.rom_name, slot))
+    }
+
+    pub fn save(&self, slot: usize, cpu: &Chip8) -> Result<PathBuf, SaveStateError> {
+        let path = self.path(slot);
+        let io_error = |source| SaveStateError::Io { path: path.clone(), source };
+
+        fs::create_dir_all(&self.dir).map_err(io_error)?;
+        fs::write(&path, cpu.save_state()).map_err(io_error)?;
+        Ok(path)
+    }
+
+    pub fn load(&self, slot: usize, cpu: &mut Chip8) -> Result<PathBuf, SaveStateError> {
+        let path = self.path(slot);
+        let bytes = fs::read(&path).map_err(|source| SaveStateError::Io { path: path.clone(), source })?;
+
+        cpu.load_state(&bytes)?;
+        Ok(path)
+    }
+}
+
+/// Appends values to a save state.
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new(rom_hash: u64) -> Self {
+        let mut writer = StateWriter { bytes: Vec::new() };
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u16(VERSION);
+        writer.u64(rom_hash);
+        writer
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // addresses and sizes all fit in 32 bits, even MEGA-CHIP's 16 MiB
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    /// A length-prefixed run of bytes.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// Reads values back in the order a [`StateWriter`] wrote them.
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header, refusing states from another format version or ROM.
+    pub(crate) fn new(bytes: &'a [u8], rom_hash: u64) -> Result<Self, SaveStateError> {
+        let bytes = bytes.strip_prefix(MAGIC).ok_or(SaveStateError::NotASaveState)?;
+        let mut reader = StateReader { bytes };
+
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        if reader.u64()? != rom_hash {
+            return Err(SaveStateError::DifferentRom);
+        }
+        Ok(reader)
+    }
+
+    /// Fails if anything is left over, which means the layout didn't match.
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        if !self.bytes.is_empty() {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if len > self.bytes.len() {
+            return Err(SaveStateError::Corrupt);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, SaveStateError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    /// A length-prefixed run that must be exactly `len` bytes long.
+    pub(crate) fn exact_bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != len {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,96 @@
+//! How fast instructions run relative to the 60 Hz timers.
+//!
+//! By default the emulator runs a fixed number of instructions per second.
+//! [`TimingMode::Vip`] instead charges every instruction what it cost on the
+//! COSMAC VIP's interpreter, so ROMs written for the original hardware run at
+//! their authentic speed without tuning `cpu_hz` per game.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::components::Instruction;
+
+// the VIP's 1802 runs at 1.76064 MHz and takes 8 clocks per machine cycle,
+// which leaves 3668 machine cycles per 60 Hz frame
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+// every frame the CDP1861 steals 128 lines of 8 bytes by DMA, and its
+// interrupt routine (timers and display pointer setup) runs for about 46 more
+pub const VIP_INTERRUPT_CYCLES: u32 = 1024 + 46;
+
+// what the interpreter spends on an instruction it has no routine for
+const VIP_DEFAULT_CYCLES: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingMode {
+    /// One instruction every `1 / cpu_hz` seconds.
+    Fixed,
+    /// Each frame runs as many instructions as fit into the VIP's spare machine cycles.
+    Vip,
+}
+
+impl fmt::Display for TimingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimingMode::Fixed => "fixed",
+            TimingMode::Vip => "vip",
+        })
+    }
+}
+
+impl FromStr for TimingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "fixed" => Ok(TimingMode::Fixed),
+            "vip" | "cosmac-vip" => Ok(TimingMode::Vip),
+            _ => Err(format!("unknown timing mode '{}' (expected fixed or vip)", s)),
+        }
+    }
+}
+
+/// Machine cycles `instruction` took on the VIP, including fetch and dispatch.
+///
+/// `registers` are the values before it ran and `skipped` whether it skipped
+/// the next instruction. DXYN is charged per row, and twice as much for rows
+/// that straddle a byte boundary and have to be shifted into two bytes; the
+/// wait for the next interrupt is left to the display wait quirk.
+pub fn vip_cycles(instruction: Instruction, registers: &[u8], skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+
+    match instruction {
+        Instruction::Clear => 24,
+        Instruction::Return | Instruction::Jump { .. } | Instruction::Call { .. } => 23,
+        Instruction::SkipEqImm { .. } | Instruction::SkipNeImm { .. } => 10 + skip,
+        Instruction::SkipEq { .. } | Instruction::SkipNe { .. } => 16 + skip,
+        Instruction::LoadImm { .. } => 6,
+        Instruction::AddImm { .. } => 10,
+        Instruction::Move { .. }
+        | Instruction::Or { .. }
+        | Instruction::And { .. }
+        | Instruction::Xor { .. }
+        | Instruction::Add { .. }
+        | Instruction::Sub { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::SubReverse { .. }
+        | Instruction::ShiftLeft { .. } => 44,
+        Instruction::LoadIndex { .. } => 12,
+        Instruction::JumpOffset { .. } => 23,
+        Instruction::Random { .. } => 36,
+        Instruction::Draw { x, n, .. } => {
+            let row_cycles = if registers[x].is_multiple_of(8) { 20 } else { 40 };
+            26 + n as u32 * row_cycles
+        }
+        Instruction::SkipKey { .. } | Instruction::SkipNotKey { .. } => 16 + skip,
+        Instruction::LoadDelay { .. }
+        | Instruction::WaitKey { .. }
+        | Instruction::SetDelay { .. }
+        | Instruction::SetSound { .. } => 10,
+        Instruction::AddIndex { .. } => 19,
+        Instruction::LoadFont { .. } => 20,
+        Instruction::StoreBcd { .. } => 204,
+        Instruction::Store { x } | Instruction::Load { x } => 14 + 8 * (x as u32 + 1),
+        _ => VIP_DEFAULT_CYCLES,
+    }
+}